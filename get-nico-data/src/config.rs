@@ -0,0 +1,135 @@
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::env;
+use clap::ArgMatches;
+use toml::value::{Table, Value};
+
+/// prefix of environment variables to override config file.
+/// e.g. NICO_OUT_FORMAT for --out-format
+const ENV_PREFIX: &str = "NICO_";
+
+/// the values of options from command line, environment variables and config file in the order.
+/// keys of config file are long names of options like out-format.
+pub(crate) struct Settings<'a> {
+    matches: &'a ArgMatches<'a>,
+    config: Table,
+    used: RefCell<HashSet<String>>,
+}
+
+impl<'a> Settings<'a> {
+    pub(crate) fn new(matches: &'a ArgMatches<'a>, config: Table) -> Settings<'a> {
+        Settings {
+            matches,
+            config,
+            used: RefCell::new(HashSet::new()),
+        }
+    }
+
+    pub(crate) fn value_of(&self, name: &str) -> Option<Cow<'a, str>> {
+        self.used.borrow_mut().insert(name.to_owned());
+        if let Some(value) = self.matches.value_of(name) {
+            return Some(Cow::Borrowed(value))
+        }
+        if let Ok(value) = env::var(env_name(name)) {
+            return Some(Cow::Owned(value))
+        }
+        self.config.get(name).map(|value| Cow::Owned(match value {
+            Value::String(value) => value.clone(),
+            Value::Array(values) => values.iter()
+                .map(|value| value.as_str().map(|x| x.to_owned()).unwrap_or_else(|| value.to_string()))
+                .collect::<Vec<_>>()
+                .join(","),
+            value => value.to_string(),
+        }))
+    }
+
+    pub(crate) fn is_present(&self, name: &str) -> bool {
+        self.used.borrow_mut().insert(name.to_owned());
+        if self.matches.is_present(name) {
+            return true
+        }
+        if let Ok(value) = env::var(env_name(name)) {
+            return value == "1" || value.eq_ignore_ascii_case("true")
+        }
+        self.config.get(name).and_then(Value::as_bool).unwrap_or(false)
+    }
+
+    /// the keys in config file which are not options
+    pub(crate) fn unknown_keys(&self) -> Vec<&str> {
+        let used = self.used.borrow();
+        self.config.keys()
+            .filter(|key| !used.contains(*key))
+            .map(|key| key.as_str())
+            .collect()
+    }
+}
+
+fn env_name(name: &str) -> String {
+    format!("{}{}", ENV_PREFIX, name.to_uppercase().replace('-', "_"))
+}
+
+/// reads the config file in toml
+pub(crate) fn read_config(path: &str) -> Result<Table, String> {
+    let text = std::fs::read_to_string(path).map_err(|err| err.to_string())?;
+    toml::from_str(&text).map_err(|err| err.to_string())
+}
+
+/// the duration which can be parsed by --timeout or other options
+pub(crate) fn duration_value(duration: std::time::Duration) -> Value {
+    if duration.subsec_nanos() == 0 {
+        Value::String(format!("{}s", duration.as_secs()))
+    } else {
+        Value::String(format!("{}ms", duration.as_millis()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::{App, Arg};
+
+    fn app() -> App<'static, 'static> {
+        ["cli-first", "env-second", "config-last", "known"].iter().fold(App::new("test"), |app, &name| {
+            app.arg(Arg::with_name(name).long(name).takes_value(true))
+        }).arg(Arg::with_name("env-flag").long("env-flag"))
+    }
+
+    fn config(text: &str) -> Table {
+        toml::from_str(text).unwrap()
+    }
+
+    #[test]
+    fn command_line_first() {
+        env::set_var("NICO_CLI_FIRST", "env");
+        let matches = app().get_matches_from(vec!["test", "--cli-first", "cli"]);
+        let settings = Settings::new(&matches, config("cli-first = \"config\""));
+        assert_eq!(settings.value_of("cli-first").as_deref(), Some("cli"));
+    }
+
+    #[test]
+    fn environment_variable_before_config() {
+        env::set_var("NICO_ENV_SECOND", "env");
+        env::set_var("NICO_ENV_FLAG", "true");
+        let matches = app().get_matches_from(vec!["test"]);
+        let settings = Settings::new(&matches, config("env-second = \"config\"\nenv-flag = false"));
+        assert_eq!(settings.value_of("env-second").as_deref(), Some("env"));
+        assert!(settings.is_present("env-flag"));
+    }
+
+    #[test]
+    fn config_last() {
+        let matches = app().get_matches_from(vec!["test"]);
+        let settings = Settings::new(&matches, config("config-last = [\"a\", \"b\"]"));
+        assert_eq!(settings.value_of("config-last").as_deref(), Some("a,b"));
+        assert_eq!(settings.value_of("known"), None);
+    }
+
+    #[test]
+    fn unknown_keys() {
+        let matches = app().get_matches_from(vec!["test"]);
+        let settings = Settings::new(&matches, config("known = 1\nknwon = 2"));
+        settings.value_of("known");
+        assert_eq!(settings.unknown_keys(), vec!["knwon"]);
+    }
+}
@@ -0,0 +1,138 @@
+use std::fmt::Write;
+use std::sync::mpsc;
+use std::time::Duration;
+use reqwest::Client;
+use serde::Serialize;
+use tokio::runtime::Runtime;
+use nico_snapshot_api::FieldName;
+use crate::Packet;
+use crate::events::{self, Event};
+use crate::get_data_from_server::{count_periods, Context, PeriodCount};
+use crate::options::{Options, OutFormat};
+use crate::progress::new_multi_progress;
+
+/// videos got at a request
+const VIDEOS_PER_REQUEST: usize = 100;
+
+/// counts videos for each period and estimates the crawl without getting videos
+pub(crate) fn dry_run(runtime: &mut Runtime, client: &Client, options: &Options) {
+    let progress = new_multi_progress(options.log_format);
+
+    let (counts, latency) = crossbeam::thread::scope(|s| {
+        s.spawn(|_| {
+            std::thread::sleep(Duration::from_secs(1));
+            progress.join().unwrap();
+        });
+        runtime.block_on(async {
+            let (sender, _receiver) = mpsc::channel::<Packet>();
+            let mut ctx = Context::new(client, &progress, options, sender);
+            let counts = count_periods(&mut ctx, options).await;
+            (counts, ctx.metrics.mean_latency())
+        })
+    }).unwrap();
+
+    let estimate = Estimate::new(counts, latency.unwrap_or_default(), options);
+    events::emit(options.log_format, Event::Estimate(&estimate));
+}
+
+/// the estimate of a crawl
+#[derive(Serialize)]
+pub(crate) struct Estimate {
+    periods: usize,
+    videos: usize,
+    requests: usize,
+    mean_latency_ms: f64,
+    duration_seconds: f64,
+    // roughly estimated from average sizes of fields
+    output_bytes: u64,
+    // not in the table since those are logged while counting
+    period_counts: Vec<PeriodCount>,
+}
+
+impl Estimate {
+    fn new(counts: Vec<PeriodCount>, latency: Duration, options: &Options) -> Estimate {
+        let mut pages = 0;
+        let mut version_requests = 0;
+        let mut videos = 0;
+        for count in &counts {
+            let period_pages = match &options.sample {
+                Some(sample) => {
+                    let sampled = sample.sampled_pages(count.count as u32, VIDEOS_PER_REQUEST as u32) as usize;
+                    videos += std::cmp::min(count.count, sampled * VIDEOS_PER_REQUEST);
+                    // with the request to count videos
+                    sampled + 1
+                }
+                None => {
+                    videos += count.count;
+                    // a request is made even if no videos are in the period
+                    std::cmp::max(1, count.count.div_ceil(VIDEOS_PER_REQUEST))
+                }
+            };
+            pages += period_pages;
+            // before and after the period and for each 100 pages
+            version_requests += 2 + (period_pages - 1) / 100;
+        }
+        // the time taken by the last request is waited before next page
+        let page_time = latency + std::cmp::max(latency, options.request_interval);
+        let duration = page_time * pages as u32 + latency * version_requests as u32;
+        Estimate {
+            periods: counts.len(),
+            videos,
+            requests: pages + version_requests,
+            mean_latency_ms: latency.as_secs_f64() * 1000.0,
+            duration_seconds: duration.as_secs_f64(),
+            output_bytes: (videos as f64 * video_bytes(options)) as u64,
+            period_counts: counts,
+        }
+    }
+
+    /// the table for human
+    pub(crate) fn table(&self) -> String {
+        let mut table = String::new();
+        let mut row = |name: &str, value: &dyn std::fmt::Display| {
+            writeln!(table, "{:<20}{:>14}", name, value).unwrap();
+        };
+        row("periods", &self.periods);
+        row("videos", &self.videos);
+        row("requests", &self.requests);
+        row("latency mean", &format!("{:.0}ms", self.mean_latency_ms));
+        row("duration", &format!("{:.1}h", self.duration_seconds / 3600.0));
+        row("output size", &format!("{:.1}MB", self.output_bytes as f64 / 1_000_000.0));
+        table.pop();
+        table
+    }
+}
+
+/// average bytes of a video in the output
+fn video_bytes(options: &Options) -> f64 {
+    let bin = options.fields.iter().map(|&field| field_bytes(field)).sum::<f64>();
+    let ratio = match options.out_format {
+        OutFormat::Bin if options.compress => 0.35,
+        OutFormat::Bin => 1.0,
+        OutFormat::Sqlite => 1.3,
+        OutFormat::Jsonl => 1.6,
+        OutFormat::Csv => 1.1,
+        OutFormat::Parquet => 0.5,
+    };
+    bin * ratio
+}
+
+/// average bytes of the field in bin
+fn field_bytes(field: FieldName) -> f64 {
+    match field {
+        FieldName::ContentId => 20.0,
+        FieldName::Title => 60.0,
+        FieldName::Description => 600.0,
+        FieldName::ViewCounter => 4.0,
+        FieldName::MylistCounter => 4.0,
+        FieldName::LengthSeconds => 8.0,
+        FieldName::ThumbnailUrl => 60.0,
+        FieldName::StartTime => 12.0,
+        FieldName::LastResBody => 120.0,
+        FieldName::CommentCounter => 4.0,
+        FieldName::LastCommentTime => 13.0,
+        FieldName::CategoryTags => 30.0,
+        FieldName::Tags => 120.0,
+        FieldName::Genre => 20.0,
+    }
+}
@@ -0,0 +1,102 @@
+use chrono::{DateTime, FixedOffset};
+use serde::Serialize;
+use structs::events::{LogEvent, LogFormat};
+use crate::metrics::Report;
+use crate::dry_run::Estimate;
+use crate::verify::VerifyReport;
+use crate::validation::ValidationReport;
+use crate::top::TopResult;
+
+/// the events reported to the user.
+/// in json log format, each event is written to stderr as a line of json.
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub(crate) enum Event<'a> {
+    PeriodStart {
+        since: DateTime<FixedOffset>,
+        until: DateTime<FixedOffset>,
+    },
+    PeriodFinish {
+        since: DateTime<FixedOffset>,
+        until: DateTime<FixedOffset>,
+        last_modified: DateTime<FixedOffset>,
+        count: usize,
+    },
+    PeriodCount {
+        since: DateTime<FixedOffset>,
+        until: DateTime<FixedOffset>,
+        count: usize,
+    },
+    PeriodDiscarded {
+        since: DateTime<FixedOffset>,
+        until: DateTime<FixedOffset>,
+    },
+    VersionChanged {
+        since: DateTime<FixedOffset>,
+        until: DateTime<FixedOffset>,
+        // None if detected after all pages got
+        got: Option<u32>,
+        // true if the videos got before are kept
+        kept: bool,
+    },
+    Retry {
+        target: &'a str,
+        reason: &'a str,
+        wait_seconds: u64,
+    },
+    Error {
+        message: &'a str,
+    },
+    Info {
+        message: &'a str,
+    },
+    Report(&'a Report),
+    Estimate(&'a Estimate),
+    Verification(&'a VerifyReport),
+    Validation(&'a ValidationReport),
+    Top(&'a TopResult),
+}
+
+impl LogEvent for Event<'_> {
+    /// None for the events shown by progress bars.
+    fn text(&self) -> Option<String> {
+        match self {
+            Event::PeriodStart { .. } => None,
+            Event::PeriodFinish { .. } => None,
+            Event::PeriodCount { since, until, count } =>
+                Some(format!("inf: {} videos since {} until {}", count, since, until)),
+            Event::PeriodDiscarded { since, .. } =>
+                Some(format!("inf: stopped. discarding the period since {}", since)),
+            Event::VersionChanged { since, got: Some(got), kept: false, .. } =>
+                Some(format!("inf: version was changed when #{}: {}", got, since)),
+            Event::VersionChanged { since, got: Some(got), kept: true, .. } =>
+                Some(format!("inf: version was changed when #{}: {}. keeping videos got before", got, since)),
+            Event::VersionChanged { since, got: None, kept: false, .. } =>
+                Some(format!("inf: version was changed at the end: {}", since)),
+            Event::VersionChanged { since, got: None, kept: true, .. } =>
+                Some(format!("inf: version was changed at the end: {}. keeping videos got before", since)),
+            Event::Retry { target, reason, .. } => Some(format!("err: {}: {}", target, reason)),
+            Event::Error { message } => Some(format!("err: {}", message)),
+            Event::Info { message } => Some(format!("inf: {}", message)),
+            Event::Report(report) => Some(report.table()),
+            Event::Estimate(estimate) => Some(estimate.table()),
+            Event::Verification(report) if report.is_matched() => Some(format!("inf: {}", report.summary())),
+            Event::Verification(report) => Some(format!("err: {}", report.summary())),
+            Event::Validation(report) if report.is_valid() => Some(format!("inf: {}", report.summary())),
+            Event::Validation(report) => Some(format!("err: {}", report.summary())),
+            Event::Top(result) if result.is_guaranteed() => Some(format!("inf: {}", result.summary())),
+            Event::Top(result) => Some(format!("err: {}", result.summary())),
+        }
+    }
+}
+
+/// writes the event to stderr. use ProgressStatus::log while progress bars are shown.
+pub(crate) fn emit(format: LogFormat, event: Event) {
+    structs::events::emit(format, event)
+}
+
+/// reports the error of the user input found after the options are parsed and exits
+pub(crate) fn exit_with_error(format: LogFormat, message: &str) -> ! {
+    emit(format, Event::Error { message });
+    std::process::exit(-1)
+}
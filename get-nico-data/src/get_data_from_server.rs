@@ -1,9 +1,10 @@
 use chrono::{DateTime, Duration, FixedOffset, Utc, Local};
-use reqwest::{StatusCode, Client, Error};
+use reqwest::{StatusCode, Client};
 use std::time::Instant;
 use indicatif::{MultiProgress};
 use crate::progress::ProgressStatus;
-use crate::options::{Options};
+use crate::options::Options;
+use structs::events::LogFormat;
 use nico_snapshot_api::*;
 use tokio::macros::support::Future;
 use std::borrow::ToOwned;
@@ -11,7 +12,17 @@ use std::mem::swap;
 use std::sync::mpsc::{Sender};
 use crate::Packet;
 use std::cmp::max;
-use tokio::time::{timeout, Elapsed};
+use tokio::time::timeout;
+use crate::interrupt::{self, is_interrupted};
+use crate::events::Event;
+use crate::metrics::Metrics;
+use crate::retry::RetryPolicy;
+use serde::Serialize;
+use crate::sample::{Sample, MAX_OFFSET};
+use crate::top::{TopCandidates, TopList};
+use crate::watchlist;
+use crate::raw::{RawPage, RawPeriod, RawStore};
+use std::collections::{HashMap, HashSet};
 
 const DATE_FORMAT: &str = "%Y/%m/%d";
 
@@ -19,39 +30,180 @@ pub(crate) struct Context<'a> {
     pub(crate) client: &'a reqwest::Client,
     pub(crate) last_req_time: Duration,
     pub(crate) progress: &'a MultiProgress,
+    pub(crate) log_format: LogFormat,
     pub(crate) sender: Sender<Packet>,
+    pub(crate) metrics: Metrics,
+    pub(crate) retry: RetryPolicy,
+    pub(crate) request_interval: std::time::Duration,
+    pub(crate) query: String,
+    pub(crate) targets: Vec<String>,
+    pub(crate) sample: Option<Sample>,
+    pub(crate) keep_mixed_versions: bool,
+    pub(crate) period_field: PeriodField,
+    // None if raw pages are not stored
+    pub(crate) raw: Option<RawStore>,
+    // content ids in the base archive. the others are got with all fields when refreshed
+    pub(crate) base_ids: HashSet<String>,
+    // --fields
+    pub(crate) fields: Vec<FieldName>,
 }
 
 impl<'a> Context<'a> {
     pub(crate) fn new(
         client: &'a reqwest::Client,
         progress: &'a MultiProgress,
+        options: &Options,
         sender: Sender<Packet>,
     ) -> Context<'a> {
         Context {
             client,
             last_req_time: Duration::zero(),
             progress,
-            sender
+            log_format: options.log_format,
+            sender,
+            metrics: Metrics::new(),
+            retry: options.retry,
+            request_interval: options.request_interval,
+            query: options.query.clone(),
+            targets: options.targets.clone(),
+            sample: options.sample,
+            keep_mixed_versions: options.keep_mixed_versions,
+            period_field: if options.recent_comments.is_some() {
+                PeriodField::LastCommentTime
+            } else {
+                PeriodField::StartTime
+            },
+            raw: options.raw_dir.as_ref().map(|dir| RawStore::new(dir, options.since.timezone())),
+            base_ids: HashSet::new(),
+            fields: options.fields.clone(),
         }
     }
 
+    pub(crate) fn new_progress(&self) -> ProgressStatus {
+        ProgressStatus::new(self.progress, self.log_format)
+    }
+
     pub(crate) fn get_wait_until(&self, _request_start: Instant) -> Instant {
         let last_req_time_since_now = Instant::now() + self.last_req_time.to_std().unwrap();
-        let ten_ms_since_now = Instant::now() + std::time::Duration::from_millis(10);
+        let interval_since_now = Instant::now() + self.request_interval;
 
-        return max(last_req_time_since_now, ten_ms_since_now);
+        return max(last_req_time_since_now, interval_since_now);
     }
 }
 
-pub(crate) async fn get_data(ctx: &mut Context<'_>, options: &Options) {
-    let since = options.since;
-    let until = options.until;
-    let per = options.duration;
+/// the field which the periods are ranges of
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub(crate) enum PeriodField {
+    StartTime,
+    // for --recent-comments
+    LastCommentTime,
+}
+
+impl PeriodField {
+    fn filter(self, since: DateTime<FixedOffset>, until: DateTime<FixedOffset>) -> FilterJson {
+        let mut filter = match self {
+            PeriodField::StartTime => RangeFilter::start_time(since, until),
+            PeriodField::LastCommentTime => RangeFilter::last_comment_time(since, until),
+        };
+        filter.include_lower();
+        FilterJson::Range(filter)
+    }
+
+    fn sorting(self) -> RankingSorting {
+        match self {
+            PeriodField::StartTime => RankingSorting::StartTime,
+            PeriodField::LastCommentTime => RankingSorting::LastCommentTime,
+        }
+    }
+}
+
+/// fields requested to refresh the videos in the base archive.
+/// large fields which are rarely changed are not requested to make the responses smaller.
+/// this saves only the bandwidth since a page is limited to 100 videos anyway.
+/// the count of requests is reduced by --refresh-since.
+const REFRESH_FIELDS: &[FieldName] = &[
+    FieldName::ContentId,
+    FieldName::Title,
+    FieldName::ViewCounter,
+    FieldName::MylistCounter,
+    FieldName::LengthSeconds,
+    FieldName::StartTime,
+    FieldName::CommentCounter,
+    FieldName::LastCommentTime,
+    FieldName::CategoryTags,
+    FieldName::Tags,
+    FieldName::Genre,
+];
+
+/// `base_newest` is the newest start time in the base archive.
+/// if specified, the videos since that are got fully and the older videos since --refresh-since are refreshed.
+/// the videos older than --refresh-since are kept as they are in the base.
+/// the videos refreshed but not in `ctx.base_ids` are got again with all fields in --fields.
+pub(crate) async fn get_data(
+    ctx: &mut Context<'_>,
+    options: &Options,
+    base_newest: Option<DateTime<FixedOffset>>,
+) {
+    match base_newest {
+        None => {
+            get_data_in(ctx, options.since, options.until, options.duration, &options.fields, false).await;
+        }
+        Some(newest) => {
+            let refresh_until = options.until.map_or(newest, |until| std::cmp::min(until, newest));
+            let refresh_fields = REFRESH_FIELDS.iter()
+                .copied()
+                .filter(|&field| options.has_field(field))
+                .collect::<Vec<_>>();
+            let refresh_since = options.refresh_since.map_or(options.since, |since| std::cmp::max(since, options.since));
+            get_data_in(ctx, refresh_since, Some(refresh_until), options.duration, &refresh_fields, true).await;
+            if !is_interrupted() {
+                get_data_in(ctx, std::cmp::max(options.since, newest), options.until, options.duration, &options.fields, false).await;
+            }
+        }
+    }
+}
+
+/// gets videos in each of the periods. stops when interrupted
+pub(crate) async fn get_periods(
+    ctx: &mut Context<'_>,
+    periods: &[(DateTime<FixedOffset>, DateTime<FixedOffset>)],
+    fields: &[FieldName],
+) {
+    let mut progress = ctx.new_progress();
+    progress.set_count(0, periods.len() as u64);
+
+    for &(since, until) in periods {
+        progress.inc();
+        progress.set_message(&format!("getting data since {} until {}",
+                                      since.format(DATE_FORMAT),
+                                      until.format(DATE_FORMAT),
+        ));
+        progress.log(Event::PeriodStart { since, until });
+
+        if do_get_for_one_period(ctx, since, until, fields, false).await.is_none() {
+            progress.log(Event::PeriodDiscarded { since, until });
+            ctx.metrics.periods_discarded += 1;
+            progress.add_info("stopped. no more periods will be got");
+            break
+        }
+    }
+}
+
+async fn get_data_in(
+    ctx: &mut Context<'_>,
+    since: DateTime<FixedOffset>,
+    until: Option<DateTime<FixedOffset>>,
+    per: Duration,
+    fields: &[FieldName],
+    refresh: bool,
+) {
+    if compute_until(until, &since.timezone()) - since < Duration::minutes(1) {
+        return
+    }
 
     let count = ((compute_until(until, &since.timezone()) - since).num_seconds() / per.num_seconds()) as u64;
 
-    let mut progress = ProgressStatus::new(&ctx.progress);
+    let mut progress = ctx.new_progress();
     progress.set_count(0, count);
 
     let mut since_n = since;
@@ -62,12 +214,20 @@ pub(crate) async fn get_data(ctx: &mut Context<'_>, options: &Options) {
                                       since_n.format(DATE_FORMAT),
                                       until_n.format(DATE_FORMAT),
         ));
+        progress.log(Event::PeriodStart { since: since_n, until: until_n });
 
-        do_get_for_one_period(
+        if do_get_for_one_period(
             ctx,
             since_n,
             until_n,
-        ).await;
+            fields,
+            refresh,
+        ).await.is_none() {
+            progress.log(Event::PeriodDiscarded { since: since_n, until: until_n });
+            ctx.metrics.periods_discarded += 1;
+            progress.add_info("stopped. no more periods will be got");
+            break
+        }
         swap(&mut until_n, &mut since_n);
         until_n = std::cmp::min(compute_until(until, &since.timezone()), since_n + per);
         if until_n - since_n < Duration::minutes(1) {
@@ -76,6 +236,238 @@ pub(crate) async fn get_data(ctx: &mut Context<'_>, options: &Options) {
     }
 }
 
+/// the count of videos in a period
+#[derive(Serialize)]
+pub(crate) struct PeriodCount {
+    pub(crate) since: DateTime<FixedOffset>,
+    pub(crate) until: DateTime<FixedOffset>,
+    pub(crate) count: usize,
+}
+
+/// counts videos for each period by requests which get no videos.
+/// the periods counted before are returned if interrupted.
+pub(crate) async fn count_periods(ctx: &mut Context<'_>, options: &Options) -> Vec<PeriodCount> {
+    let until = compute_until(options.until, &options.since.timezone());
+    let mut counts = Vec::new();
+    if until - options.since < Duration::minutes(1) {
+        return counts
+    }
+
+    let mut progress = ctx.new_progress();
+    progress.set_count(0, ((until - options.since).num_seconds() / options.duration.num_seconds()) as u64);
+
+    let mut since_n = options.since;
+    while until - since_n >= Duration::minutes(1) {
+        let until_n = std::cmp::min(until, since_n + options.duration);
+        progress.inc();
+        progress.set_message(&format!("counting videos since {} until {}",
+                                      since_n.format(DATE_FORMAT),
+                                      until_n.format(DATE_FORMAT),
+        ));
+
+        let mut params = QueryParams::new(&ctx.query, RankingSorting::StartTime.increasing());
+        params.with_targets(&ctx.targets.iter().map(|x| x.as_str()).collect::<Vec<_>>());
+        params.with_fields(&[FieldName::ContentId]);
+        params.set_filter(FilterJson::Range(
+            RangeFilter::start_time(since_n, until_n)
+                .include_lower()
+                .to_owned()));
+        params.set_limit(0);
+
+        let params = &params;
+        let json = match http_request(
+            ctx,
+            &mut progress,
+            || { format!("{}..{}#count", since_n.format(DATE_FORMAT), until_n.format(DATE_FORMAT)) },
+            move |cli| { async move { params.get(cli).await } }
+        ).await {
+            Some((json, duration)) => {
+                ctx.last_req_time = Duration::from_std(duration).unwrap();
+                json
+            }
+            None => break,
+        };
+        progress.log(Event::PeriodCount { since: since_n, until: until_n, count: json.meta.total_count });
+        counts.push(PeriodCount { since: since_n, until: until_n, count: json.meta.total_count });
+
+        interrupt::delay_until(ctx.get_wait_until(Instant::now())).await;
+        since_n = until_n;
+    }
+    counts
+}
+
+/// gets the videos commented in the window until now.
+/// the periods are ranges of lastCommentTime instead of startTime.
+pub(crate) async fn get_recent_comments(ctx: &mut Context<'_>, options: &Options, window: std::time::Duration) {
+    let until = Utc::now().with_timezone(&options.since.timezone());
+    let since = until - Duration::from_std(window).unwrap();
+    get_data_in(ctx, since, Some(until), options.duration, &options.fields, false).await;
+}
+
+/// gets the heads of the lists sorted by view counter and by length until
+/// the top videos by watch sum are guaranteed, and sends the top videos.
+/// the result is also logged. returns None if interrupted.
+pub(crate) async fn get_top(ctx: &mut Context<'_>, options: &Options, count: usize) -> Option<()> {
+    const PAGE_SIZE: u32 = 100;
+    let since = options.since;
+    let until = compute_until(options.until, &since.timezone());
+
+    let mut fields = options.fields.clone();
+    for &required in &[FieldName::ViewCounter, FieldName::LengthSeconds] {
+        if !fields.contains(&required) {
+            fields.push(required);
+        }
+    }
+    let new_params = |sorting: RankingSorting| {
+        let mut params = QueryParams::new(&ctx.query, sorting.decreasing());
+        params.with_targets(&ctx.targets.iter().map(|x| x.as_str()).collect::<Vec<_>>());
+        params.with_fields(&fields);
+        params.set_filter(FilterJson::Range(
+            RangeFilter::start_time(since, until)
+                .include_lower()
+                .to_owned()));
+        params.set_limit(PAGE_SIZE);
+        params
+    };
+    let mut by_views = new_params(RankingSorting::ViewCounter);
+    let mut by_length = new_params(RankingSorting::LengthSeconds);
+
+    let mut progress = ctx.new_progress();
+    loop {
+        progress.set_message("getting version before get...");
+        let pre_version = get_snapshot_version(ctx).await?;
+
+        let mut candidates = TopCandidates::new(count);
+        let mut offsets = [0, 0];
+        let mut offset_limited = false;
+        while !candidates.is_guaranteed() {
+            // the lists are got alternately
+            let list = if offsets[0] <= offsets[1] { TopList::ByViews } else { TopList::ByLength };
+            let index = if list == TopList::ByViews { 0 } else { 1 };
+            let offset = offsets[index];
+            if offset > MAX_OFFSET {
+                offset_limited = true;
+                break
+            }
+            progress.set_message(&format!("getting top by {} #{}",
+                                          if list == TopList::ByViews { "views" } else { "length" }, offset));
+
+            let request_start = Instant::now();
+            let params = if list == TopList::ByViews { &mut by_views } else { &mut by_length };
+            params.set_offset(offset);
+            let params = &*params;
+            let (json, duration) = http_request(
+                ctx,
+                &mut progress,
+                || { format!("top#{}", offset) },
+                move |cli| { async move { params.get(cli).await } }
+            ).await?;
+            ctx.last_req_time = Duration::from_std(duration).unwrap();
+
+            let exhausted = json.data.len() < PAGE_SIZE as usize;
+            offsets[index] += PAGE_SIZE;
+            candidates.add(list, json.data, exhausted);
+
+            interrupt::delay_until(ctx.get_wait_until(request_start)).await;
+            if is_interrupted() {
+                return None
+            }
+        }
+
+        progress.set_message("getting version after get...");
+        let post_version = get_snapshot_version(ctx).await?;
+        if pre_version != post_version {
+            progress.log(Event::VersionChanged { since, until, got: None, kept: false });
+            ctx.metrics.version_changes += 1;
+            continue
+        }
+
+        let result = candidates.result(pre_version.last_modified, offset_limited);
+        progress.log(Event::Top(&result));
+        let videos = candidates.into_top();
+        ctx.metrics.periods_finished += 1;
+        ctx.metrics.videos += videos.len() as u64;
+        ctx.sender.send(Packet {
+            since,
+            until,
+            last_modified: pre_version.last_modified,
+            total_count: videos.len(),
+            videos,
+            versions: None,
+            refresh: false,
+        }).unwrap();
+        return Some(())
+    }
+}
+
+/// gets the videos of the content ids in batches and sends them at once.
+/// the ids not found are logged. returns None if interrupted.
+pub(crate) async fn get_watchlist(ctx: &mut Context<'_>, options: &Options, ids: &[String]) -> Option<()> {
+    let since = options.since;
+    let until = compute_until(options.until, &since.timezone());
+
+    let mut params = QueryParams::new(&ctx.query, RankingSorting::StartTime.increasing());
+    params.with_targets(&ctx.targets.iter().map(|x| x.as_str()).collect::<Vec<_>>());
+    params.with_fields(&options.fields);
+    params.set_limit(watchlist::BATCH_SIZE as u32);
+
+    let mut progress = ctx.new_progress();
+    loop {
+        progress.set_message("getting version before get...");
+        let pre_version = get_snapshot_version(ctx).await?;
+
+        let mut vec = Vec::<VideoInfo>::with_capacity(ids.len());
+        let batches = ids.chunks(watchlist::BATCH_SIZE).collect::<Vec<_>>();
+        for (index, batch) in batches.iter().enumerate() {
+            progress.set_count(index as u64, batches.len() as u64);
+            progress.set_message(&format!("getting watchlist #{}", index * watchlist::BATCH_SIZE));
+
+            let request_start = Instant::now();
+            params.set_filter(watchlist::filter_of(batch));
+            let params = &params;
+            let (json, duration) = http_request(
+                ctx,
+                &mut progress,
+                || { format!("watchlist#{}", index * watchlist::BATCH_SIZE) },
+                move |cli| { async move { params.get(cli).await } }
+            ).await?;
+            ctx.last_req_time = Duration::from_std(duration).unwrap();
+            vec.extend(json.data);
+
+            interrupt::delay_until(ctx.get_wait_until(request_start)).await;
+            if is_interrupted() {
+                return None
+            }
+        }
+
+        progress.set_message("getting version after get...");
+        let post_version = get_snapshot_version(ctx).await?;
+        if pre_version != post_version {
+            progress.log(Event::VersionChanged { since, until, got: None, kept: false });
+            ctx.metrics.version_changes += 1;
+            continue
+        }
+
+        if vec.len() < ids.len() {
+            progress.log(Event::Info {
+                message: &format!("{} of {} videos in watchlist are not found", ids.len() - vec.len(), ids.len()),
+            });
+        }
+        ctx.metrics.periods_finished += 1;
+        ctx.metrics.videos += vec.len() as u64;
+        ctx.sender.send(Packet {
+            since,
+            until,
+            last_modified: pre_version.last_modified,
+            total_count: vec.len(),
+            videos: vec,
+            versions: None,
+            refresh: false,
+        }).unwrap();
+        return Some(())
+    }
+}
+
 fn compute_until<Tz: chrono::TimeZone>(until: Option<DateTime<Tz>>, tz: &Tz) -> DateTime<Tz> {
     match until {
         None => Utc::now().with_timezone(tz),
@@ -83,39 +475,62 @@ fn compute_until<Tz: chrono::TimeZone>(until: Option<DateTime<Tz>>, tz: &Tz) ->
     }
 }
 
+/// returns None if interrupted. the period is discarded in that case.
 async fn do_get_for_one_period(
     ctx: &mut Context<'_>,
     since: DateTime<FixedOffset>,
     until: DateTime<FixedOffset>,
-) {
-    let mut progress = ProgressStatus::new(&ctx.progress);
+    fields: &[FieldName],
+    refresh: bool,
+) -> Option<()> {
+    let mut progress = ctx.new_progress();
 
-    let filter = FilterJson::Range(
-        RangeFilter::start_time(since, until)
-            .include_lower()
-            .to_owned());
+    let filter = ctx.period_field.filter(since, until);
 
 
-    let mut params = QueryParams::new("", RankingSorting::StartTime.increasing());
-    params.with_fields(FieldName::all_values());
+    let mut params = QueryParams::new(&ctx.query, ctx.period_field.sorting().increasing());
+    params.with_targets(&ctx.targets.iter().map(|x| x.as_str()).collect::<Vec<_>>());
+    params.with_fields(fields);
     params.set_filter(filter);
     params.set_limit(100);
 
     'outer: loop {
         progress.set_message(&format!("getting version before get..."));
-        let pre_version = get_snapshot_version(ctx).await;
+        let pre_version = get_snapshot_version(ctx).await?;
+        if let Some(raw) = &ctx.raw {
+            raw.start_period(since, until);
+        }
 
         let mut vec = Vec::<VideoInfo>::new();
+        let mut raw_pages = Vec::<RawPage>::new();
+        // the version each video is got under. the version checked last before the page is used
+        let mut versions = Vec::<DateTime<FixedOffset>>::new();
+        let mut version = pre_version;
         let mut loop_counter: u32 = 0;
         let mut got: u32 = 0;
         let mut full_count = 1;
+        if let Some(sample) = ctx.sample {
+            // the loop below is skipped since the sampled pages are got instead of all pages
+            let (sampled, count, offsets) = get_sample_pages(ctx, &mut progress, &mut params, since, until, sample).await?;
+            versions = vec![pre_version.last_modified; sampled.len()];
+            raw_pages = offsets.into_iter()
+                .map(|offset| RawPage { offset, last_modified: pre_version.last_modified })
+                .collect();
+            vec = sampled;
+            got = count;
+            full_count = count;
+        }
         while got < full_count {
             if loop_counter % 100 == 100-1 {
                 progress.set_msg_keeping_prefix(format!("getting version after 100 loop..."));
-                let post_version = get_snapshot_version(ctx).await;
-                if pre_version != post_version {
-                    progress.add_info(&format!("version was changed when #{}: {}", got, since));
-                    continue'outer
+                let post_version = get_snapshot_version(ctx).await?;
+                if version != post_version {
+                    progress.log(Event::VersionChanged { since, until, got: Some(got), kept: ctx.keep_mixed_versions });
+                    ctx.metrics.version_changes += 1;
+                    if !ctx.keep_mixed_versions {
+                        continue'outer
+                    }
+                    version = post_version;
                 }
             }
             loop_counter += 1;
@@ -128,14 +543,17 @@ async fn do_get_for_one_period(
             params.set_offset(got);
 
             let params = &params;
-            let (json, duration) = http_request(
+            let raw = ctx.raw.is_some();
+            let ((json, body), duration) = http_request(
                 ctx,
                 &mut progress,
-                5,
-                1,
                 || { format!("{}..{}#{}", since.format(DATE_FORMAT), until.format(DATE_FORMAT), got) },
-                move |cli| { async move { params.get(&cli).await } }
-            ).await;
+                move |cli| { async move { get_page(params, cli, raw).await } }
+            ).await?;
+            if let (Some(raw), Some(body)) = (&ctx.raw, &body) {
+                raw.write_page(since, until, got, body);
+                raw_pages.push(RawPage { offset: got, last_modified: version.last_modified });
+            }
 
             if vec.capacity() > json.meta.total_count {
                 vec.reserve(json.meta.total_count - vec.len())
@@ -149,109 +567,301 @@ async fn do_get_for_one_period(
             full_count = json.meta.total_count as u32;
             got += len;
             for x in json.data {
-                vec.push(x)
+                vec.push(x);
+                versions.push(version.last_modified);
             }
 
             let until = ctx.get_wait_until(request_start);
             progress.set_msg_keeping_prefix(format!("waiting for server load reduction since {} until {}",
                                                     Local::now(),
                                                     Local::now() + Duration::from_std(until - Instant::now()).unwrap()));
-            tokio::time::delay_until(ctx.get_wait_until(request_start).into())
+            interrupt::delay_until(ctx.get_wait_until(request_start))
                 .await;
+            if is_interrupted() {
+                return None
+            }
             let finished = len == 0;
             if finished {
                 break;
             }
         }
 
+        let mut refetch_pages = Vec::<RawPage>::new();
+        if refresh {
+            get_not_in_base(ctx, &mut progress, since, until, version.last_modified, &mut vec, &mut refetch_pages).await?;
+        }
+
         progress.set_message(&format!("getting version after get..."));
-        let post_version = get_snapshot_version(ctx).await;
-        if pre_version != post_version {
-            progress.add_info(&format!("version was changed at the end: {}", since));
-            continue
-        } else {
-            ctx.sender.send(Packet {
-                last_modified: pre_version.last_modified,
-                videos: vec
-            }).unwrap();
-            return
+        let post_version = get_snapshot_version(ctx).await?;
+        if version != post_version {
+            progress.log(Event::VersionChanged { since, until, got: None, kept: ctx.keep_mixed_versions });
+            ctx.metrics.version_changes += 1;
+            if !ctx.keep_mixed_versions {
+                continue
+            }
+        }
+        let mixed = pre_version != post_version;
+        if mixed {
+            ctx.metrics.periods_mixed += 1;
+        }
+        progress.log(Event::PeriodFinish {
+            since,
+            until,
+            last_modified: post_version.last_modified,
+            count: vec.len(),
+        });
+        ctx.metrics.periods_finished += 1;
+        ctx.metrics.videos += vec.len() as u64;
+        if let Some(raw) = &ctx.raw {
+            raw.finish_period(&RawPeriod {
+                since,
+                until,
+                last_modified: post_version.last_modified,
+                total_count: full_count as usize,
+                refresh,
+                pages: raw_pages,
+                refetch: refetch_pages,
+            });
+        }
+        ctx.sender.send(Packet {
+            since,
+            until,
+            last_modified: post_version.last_modified,
+            videos: vec,
+            versions: if mixed { Some(versions) } else { None },
+            total_count: full_count as usize,
+            refresh,
+        }).unwrap();
+        return Some(())
+    }
+}
+
+/// replaces the videos refreshed but not in the base archive with the ones got with all fields in --fields
+/// because the fields not refreshed can't be taken from the base.
+async fn get_not_in_base(
+    ctx: &mut Context<'_>,
+    progress: &mut ProgressStatus,
+    since: DateTime<FixedOffset>,
+    until: DateTime<FixedOffset>,
+    version: DateTime<FixedOffset>,
+    videos: &mut [VideoInfo],
+    raw_pages: &mut Vec<RawPage>,
+) -> Option<()> {
+    let ids = videos.iter()
+        .map(|video| video.content_id.clone().unwrap())
+        .filter(|id| !ctx.base_ids.contains(id))
+        .collect::<Vec<_>>();
+    if ids.is_empty() {
+        return Some(())
+    }
+
+    let mut params = QueryParams::new(&ctx.query, RankingSorting::StartTime.increasing());
+    params.with_targets(&ctx.targets.iter().map(|x| x.as_str()).collect::<Vec<_>>());
+    params.with_fields(&ctx.fields);
+    params.set_limit(watchlist::BATCH_SIZE as u32);
+
+    let mut got = Vec::<VideoInfo>::with_capacity(ids.len());
+    for (index, batch) in ids.chunks(watchlist::BATCH_SIZE).enumerate() {
+        progress.set_msg_keeping_prefix(format!("getting {} videos not in base...", ids.len()));
+
+        let request_start = Instant::now();
+        let offset = (index * watchlist::BATCH_SIZE) as u32;
+        params.set_filter(watchlist::filter_of(batch));
+        let params = &params;
+        let raw = ctx.raw.is_some();
+        let ((json, body), duration) = http_request(
+            ctx,
+            progress,
+            || { format!("not in base#{}", offset) },
+            move |cli| { async move { get_page(params, cli, raw).await } }
+        ).await?;
+        if let (Some(raw), Some(body)) = (&ctx.raw, &body) {
+            raw.write_refetch(since, until, offset, body);
+            raw_pages.push(RawPage { offset, last_modified: version });
+        }
+        ctx.last_req_time = Duration::from_std(duration).unwrap();
+        got.extend(json.data);
+
+        interrupt::delay_until(ctx.get_wait_until(request_start)).await;
+        if is_interrupted() {
+            return None
+        }
+    }
+
+    replace_refetched(videos, got);
+    Some(())
+}
+
+/// replaces the videos with the ones of the same content id got again
+pub(crate) fn replace_refetched(videos: &mut [VideoInfo], refetched: Vec<VideoInfo>) {
+    let mut refetched = refetched.into_iter()
+        .map(|video| (video.content_id.clone().unwrap(), video))
+        .collect::<HashMap<_, _>>();
+    for video in videos.iter_mut() {
+        if let Some(full) = refetched.remove(video.content_id.as_ref().unwrap()) {
+            *video = full;
         }
     }
 }
 
-async fn get_snapshot_version(ctx: &mut Context<'_>) -> SnapshotVersion
+/// gets randomly chosen pages of the period.
+/// returns the videos, the count of videos expected in the pages and the offsets of the pages.
+async fn get_sample_pages(
+    ctx: &mut Context<'_>,
+    progress: &mut ProgressStatus,
+    params: &mut QueryParams,
+    since: DateTime<FixedOffset>,
+    until: DateTime<FixedOffset>,
+    sample: Sample,
+) -> Option<(Vec<VideoInfo>, u32, Vec<u32>)> {
+    const PAGE_SIZE: u32 = 100;
+
+    progress.set_msg_keeping_prefix("counting videos...");
+    params.set_offset(0);
+    params.set_limit(0);
+    let (json, _) = {
+        let params = &*params;
+        http_request(
+            ctx,
+            progress,
+            || { format!("{}..{}#count", since.format(DATE_FORMAT), until.format(DATE_FORMAT)) },
+            move |cli| { async move { params.get(cli).await } }
+        ).await?
+    };
+    let total_count = json.meta.total_count as u32;
+    params.set_limit(PAGE_SIZE);
+
+    let offsets = sample.choose_offsets(total_count, PAGE_SIZE, &mut sample.rng_of(since, until));
+    let expected = offsets.iter().map(|&offset| std::cmp::min(PAGE_SIZE, total_count - offset)).sum();
+    let mut vec = Vec::<VideoInfo>::new();
+    for (index, &offset) in offsets.iter().enumerate() {
+        progress.set_count(index as u64, offsets.len() as u64);
+        let request_start = Instant::now();
+        params.set_offset(offset);
+
+        let params = &*params;
+        let raw = ctx.raw.is_some();
+        let ((json, body), duration) = http_request(
+            ctx,
+            progress,
+            || { format!("{}..{}#{}", since.format(DATE_FORMAT), until.format(DATE_FORMAT), offset) },
+            move |cli| { async move { get_page(params, cli, raw).await } }
+        ).await?;
+        if let (Some(raw), Some(body)) = (&ctx.raw, &body) {
+            raw.write_page(since, until, offset, body);
+        }
+        ctx.last_req_time = Duration::from_std(duration).unwrap();
+        vec.extend(json.data);
+
+        progress.set_msg_keeping_prefix("waiting for server load reduction...");
+        interrupt::delay_until(ctx.get_wait_until(request_start)).await;
+        if is_interrupted() {
+            return None
+        }
+    }
+    Some((vec, expected, offsets))
+}
+
+/// gets the page. the body is returned too if raw pages are stored
+async fn get_page(params: &QueryParams, client: &Client, raw: bool) -> Result<(ResponseJson, Option<String>), RequestError> {
+    if raw {
+        let (json, body) = params.get_raw(client).await?;
+        Ok((json, Some(body)))
+    } else {
+        Ok((params.get(client).await?, None))
+    }
+}
+
+pub(crate) async fn get_snapshot_version(ctx: &mut Context<'_>) -> Option<SnapshotVersion>
 {
     //*
-    let mut progress = ProgressStatus::new(&ctx.progress);
+    let mut progress = ctx.new_progress();
     progress.set_prefix("snapshot_version");
 
     let (version, _) = http_request(
         ctx,
         &mut progress,
-        1,
-        1,
         || { format!("snapshot version") },
         move |cli| { async move { snapshot_version(&cli).await } },
-    ).await;
+    ).await?;
 
-    return version;
+    return Some(version);
 }
 
-async fn http_request<'a, Fut: Future<Output = reqwest::Result<R>> + 'a, R>(
-    ctx: &Context<'a>,
+/// returns None if interrupted or given up by the retry policy.
+async fn http_request<'a, Fut: Future<Output = Result<R, RequestError>>, R>(
+    ctx: &mut Context<'a>,
     progress: &mut ProgressStatus,
-    minutes_for_wait_5xx: i64,
-    minutes_for_wait_unknown: i64,
     get_name: impl Fn() -> String,
     get: impl Fn(&'a Client) -> Fut,
-) -> (R, tokio::time::Duration) {
+) -> Option<(R, tokio::time::Duration)> {
+    let first_attempt = Instant::now();
+    let mut failures = 0;
     loop {
+        if is_interrupted() {
+            return None
+        }
         progress.set_msg_keeping_prefix(&format!("waiting response..."));
         let request_start = Instant::now();
-        let response = match timeout(std::time::Duration::from_secs(10), get(ctx.client)).await {
-            Ok(r) => r,
+        ctx.metrics.requests += 1;
+        let (reason, retry_after) = match timeout(ctx.retry.timeout, get(ctx.client)).await {
             Err(err) => {
-                progress.add_err(&format!("time out getting {}: {}", get_name(), err));
-                continue
+                ctx.metrics.timeouts += 1;
+                (format!("time out: {}", err), None)
             }
-        };
-        match response {
-            Ok(value) => {
+            Ok(Ok(value)) => {
                 let request_end = Instant::now();
-                return (value, request_end - request_start);
+                ctx.metrics.add_latency(request_end - request_start);
+                return Some((value, request_end - request_start));
             }
-            Err(err) => {
-                if let Some(code) = err.status() {
-                    match code {
-                        | StatusCode::INTERNAL_SERVER_ERROR
-                        | StatusCode::NOT_IMPLEMENTED
-                        | StatusCode::BAD_GATEWAY
-                        | StatusCode::SERVICE_UNAVAILABLE
-                        | StatusCode::GATEWAY_TIMEOUT
-                        | StatusCode::HTTP_VERSION_NOT_SUPPORTED
-                        | StatusCode::VARIANT_ALSO_NEGOTIATES
-                        | StatusCode::INSUFFICIENT_STORAGE
-                        | StatusCode::LOOP_DETECTED
-                        | StatusCode::NOT_EXTENDED
-                        | StatusCode::NETWORK_AUTHENTICATION_REQUIRED
-                        => {
-                            let err = format!("known 5xx status so wait for {} minutes: {}", minutes_for_wait_5xx, code);
-                            progress.add_err(&format!("{}: {}", get_name(), err));
-                            progress.set_msg_keeping_prefix(err);
-                            tokio::time::delay_for(Duration::minutes(minutes_for_wait_5xx).to_std().unwrap()).await;
-                            continue
-                        }
-                        _ => {
-                            progress.add_err(&format!("unknown response: {}", code));
-
-                            progress.set_msg_keeping_prefix(format!("unknown status so wait for {} minutes: {}", minutes_for_wait_unknown, code));
-                            tokio::time::delay_for(Duration::minutes(minutes_for_wait_unknown).to_std().unwrap()).await;
-                        }
-                    }
-                } else {
-                    progress.add_err(&format!("unknown error: {}", err));
+            Ok(Err(err)) => match err.status() {
+                Some(code) if is_known_5xx(code) => {
+                    ctx.metrics.server_errors += 1;
+                    (format!("known 5xx status: {}", code), err.retry_after())
+                }
+                Some(code) => {
+                    ctx.metrics.unknown_statuses += 1;
+                    (format!("unknown response: {}", code), err.retry_after())
+                }
+                None => {
+                    ctx.metrics.unknown_errors += 1;
+                    (format!("unknown error: {}", err), None)
                 }
             }
+        };
+
+        failures += 1;
+        let wait = ctx.retry.wait(failures, retry_after);
+        if ctx.retry.is_exhausted(failures, first_attempt.elapsed() + wait) {
+            progress.log(Event::Error {
+                message: &format!("{}: {}: gave up after {} attempts in {} seconds",
+                                  get_name(), reason, failures, first_attempt.elapsed().as_secs()),
+            });
+            interrupt::give_up();
+            return None
         }
+        progress.log(Event::Retry {
+            target: &get_name(),
+            reason: &reason,
+            wait_seconds: wait.as_secs(),
+        });
+        progress.set_msg_keeping_prefix(format!("{} so wait for {} seconds", reason, wait.as_secs()));
+        interrupt::delay_for(wait).await;
     }
 }
+
+fn is_known_5xx(code: StatusCode) -> bool {
+    matches!(code,
+        | StatusCode::INTERNAL_SERVER_ERROR
+        | StatusCode::NOT_IMPLEMENTED
+        | StatusCode::BAD_GATEWAY
+        | StatusCode::SERVICE_UNAVAILABLE
+        | StatusCode::GATEWAY_TIMEOUT
+        | StatusCode::HTTP_VERSION_NOT_SUPPORTED
+        | StatusCode::VARIANT_ALSO_NEGOTIATES
+        | StatusCode::INSUFFICIENT_STORAGE
+        | StatusCode::LOOP_DETECTED
+        | StatusCode::NOT_EXTENDED
+        | StatusCode::NETWORK_AUTHENTICATION_REQUIRED
+    )
+}
@@ -0,0 +1,101 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::process::exit;
+use std::time::{Duration, Instant};
+use std::cmp::min;
+use structs::events::LogFormat;
+use crate::events::{self, Event};
+
+/// exit status used when crawling was stopped by SIGINT or SIGTERM
+pub(crate) const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+/// exit status used when a request was given up by the retry policy
+pub(crate) const GAVE_UP_EXIT_CODE: i32 = 1;
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+static GAVE_UP: AtomicBool = AtomicBool::new(false);
+
+/// true if crawling should be stopped by signals or giving up
+pub(crate) fn is_interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst) || is_gave_up()
+}
+
+pub(crate) fn is_gave_up() -> bool {
+    GAVE_UP.load(Ordering::SeqCst)
+}
+
+/// stops crawling as same as interrupted by signals
+pub(crate) fn give_up() {
+    GAVE_UP.store(true, Ordering::SeqCst)
+}
+
+/// lets the next crawl run after a crawl was given up
+pub(crate) fn clear_gave_up() {
+    GAVE_UP.store(false, Ordering::SeqCst)
+}
+
+/// waits for SIGINT or SIGTERM forever.
+/// the first signal requests to stop crawling, and the second one exits immediately.
+pub(crate) async fn watch_signals(format: LogFormat) {
+    let mut signals = Signals::new();
+    loop {
+        signals.recv().await;
+        if INTERRUPTED.swap(true, Ordering::SeqCst) {
+            events::emit(format, Event::Error { message: "interrupted again. exiting without writing" });
+            exit(INTERRUPTED_EXIT_CODE)
+        }
+        events::emit(format, Event::Info { message: "interrupted. stopping after writing finished periods..." });
+    }
+}
+
+/// same as tokio::time::delay_until but returns early if interrupted
+pub(crate) async fn delay_until(deadline: Instant) {
+    while !is_interrupted() {
+        let now = Instant::now();
+        if deadline <= now {
+            return
+        }
+        tokio::time::delay_for(min(deadline - now, Duration::from_millis(500))).await;
+    }
+}
+
+pub(crate) async fn delay_for(duration: Duration) {
+    delay_until(Instant::now() + duration).await
+}
+
+#[cfg(unix)]
+struct Signals {
+    interrupt: tokio::signal::unix::Signal,
+    terminate: tokio::signal::unix::Signal,
+}
+
+#[cfg(unix)]
+impl Signals {
+    fn new() -> Signals {
+        use tokio::signal::unix::{signal, SignalKind};
+        Signals {
+            interrupt: signal(SignalKind::interrupt()).unwrap(),
+            terminate: signal(SignalKind::terminate()).unwrap(),
+        }
+    }
+
+    async fn recv(&mut self) {
+        tokio::select! {
+            _ = self.interrupt.recv() => {}
+            _ = self.terminate.recv() => {}
+        }
+    }
+}
+
+#[cfg(not(unix))]
+struct Signals;
+
+#[cfg(not(unix))]
+impl Signals {
+    fn new() -> Signals {
+        Signals
+    }
+
+    async fn recv(&mut self) {
+        tokio::signal::ctrl_c().await.unwrap()
+    }
+}
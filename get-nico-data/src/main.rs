@@ -2,52 +2,200 @@ mod progress;
 mod options;
 mod get_data_from_server;
 mod output;
+mod interrupt;
+mod events;
+mod metrics;
+mod retry;
+mod watch;
+mod validation;
+mod config;
+mod dry_run;
+mod sample;
+mod verify;
+mod top;
+mod watchlist;
+mod plan;
+mod raw;
 
 use chrono::{DateTime, FixedOffset, TimeZone};
-use indicatif::{MultiProgress};
-use crate::options::{parse_options};
+use crate::options::{parse_options, Options};
 use nico_snapshot_api::VideoInfo;
 use std::sync::mpsc;
-use crate::get_data_from_server::{get_data, Context};
-
-const DEFAULT_USER_AGENT: &str = concat!("view-counter-times-video-length-ranking-getting-daemon/", env!("CARGO_PKG_VERSION"));
+use crate::get_data_from_server::{get_data, get_periods, get_recent_comments, get_top, get_watchlist, Context};
+use crate::interrupt::{is_interrupted, is_gave_up, INTERRUPTED_EXIT_CODE, GAVE_UP_EXIT_CODE};
+use std::process::exit;
+use std::fs::{create_dir_all, File};
+use std::path::Path;
+use crate::events::Event;
+use crate::progress::new_multi_progress;
+use tokio::runtime::Runtime;
 
 struct Packet {
+    since: DateTime<FixedOffset>,
+    until: DateTime<FixedOffset>,
     last_modified: DateTime<FixedOffset>,
     videos: Vec<VideoInfo>,
+    // the version each video is got under. None if all videos are got under last_modified
+    versions: Option<Vec<DateTime<FixedOffset>>>,
+    // meta.total_count of the period
+    total_count: usize,
+    // true if the videos are got to refresh the base archive
+    refresh: bool,
+}
+
+impl Packet {
+    /// the version the video at the index is got under
+    fn version_of(&self, index: usize) -> DateTime<FixedOffset> {
+        self.versions.as_ref().map_or(self.last_modified, |versions| versions[index])
+    }
 }
 
 fn main() {
     let options = parse_options();
 
-    let client = reqwest::Client::builder()
-        .user_agent(DEFAULT_USER_AGENT)
-        .build().unwrap();
+    if options.print_config {
+        print!("{}", toml::to_string(&options.to_config()).unwrap());
+        return
+    }
 
-    let progress = MultiProgress::new();
+    if let Some(path) = &options.convert {
+        output::convert(&options, path);
+        return
+    }
 
-    crossbeam::thread::scope(|s| {
-        let (sender, receiver) = mpsc::channel::<Packet>();
-        s.spawn(|_| {
-            tokio::runtime::Builder::new()
-                .threaded_scheduler()
-                .enable_all()
-                .build()
-                .unwrap()
-                .block_on(async {
-                    let mut ctx = Context::new(&client, &progress, sender);
-                    get_data(&mut ctx, &options).await;
-                    ctx.sender.send(Packet{ last_modified: FixedOffset::east(0).timestamp(0, 0), videos: Vec::new() }).unwrap();
-                    eprintln!("finished main thread");
-                })
+    if let Some(dir) = &options.rebuild_from_raw {
+        rebuild(&options, dir);
+        return
+    }
+
+    if let Some(path) = &options.plan_out {
+        plan::write_plan(&plan::Plan::new(&options, options.shards), path);
+        return
+    }
+
+    if let Some(plan) = &options.plan {
+        if !options.merge_shards.is_empty() {
+            if !plan::merge(&options, plan, &options.merge_shards) {
+                exit(plan::INCONSISTENT_EXIT_CODE)
+            }
+            return
+        }
+    }
+
+    let mut client = reqwest::Client::builder()
+        .user_agent(&options.user_agent);
+    if let Some(proxy) = &options.proxy {
+        client = client.proxy(reqwest::Proxy::all(proxy).unwrap());
+    }
+    let client = client.build().unwrap();
+
+    let mut runtime = tokio::runtime::Builder::new()
+        .threaded_scheduler()
+        .enable_all()
+        .build()
+        .unwrap();
+    runtime.spawn(interrupt::watch_signals(options.log_format));
+
+    if let Some(path) = &options.verify {
+        let verified = verify::verify(&mut runtime, &client, &options, path);
+        if let Some(code) = verified.exit_code() {
+            if !is_interrupted() {
+                exit(code)
+            }
+        }
+    } else if options.dry_run {
+        dry_run::dry_run(&mut runtime, &client, &options);
+    } else if options.watch {
+        watch::watch(&mut runtime, &client, &options);
+    } else {
+        crawl(&mut runtime, &client, &options);
+        if !is_interrupted() {
+            if let Some(then) = &options.then {
+                watch::run_then(then, &options, None);
+            }
+        }
+    }
+
+    if is_gave_up() {
+        events::emit(options.log_format, Event::Error {
+            message: "a request was given up by the retry policy. videos got before were written",
         });
+        exit(GAVE_UP_EXIT_CODE)
+    }
+    if is_interrupted() {
+        exit(INTERRUPTED_EXIT_CODE)
+    }
+}
+
+/// writes the videos in the raw pages to the output as a crawl does
+fn rebuild(options: &Options, dir: &str) {
+    let base = output::read_base(options);
+    let (sender, receiver) = mpsc::channel::<Packet>();
+    raw::rebuild(dir, &sender)
+        .unwrap_or_else(|err| events::exit_with_error(options.log_format, &format!("rebuild-from-raw: {}", err)));
+    drop(sender);
+    output::run(receiver, options, base.unwrap_or_default());
+}
+
+/// gets videos and writes them to the output and the report
+fn crawl(runtime: &mut Runtime, client: &reqwest::Client, options: &Options) {
+    let progress = new_multi_progress(options.log_format);
+
+    let base = output::read_base(options);
+    let base_newest = base.as_ref()
+        .and_then(|base| base.iter().map(|video| video.start_time).max())
+        .map(|newest| newest.with_timezone(&options.since.timezone()));
+    let base_ids = base.iter().flatten().map(|video| video.content_id.clone()).collect();
+
+    let watchlist = options.watchlist.as_ref().map(|path| watchlist::read_ids(path, options.log_format));
+
+    let metrics = crossbeam::thread::scope(|s| {
+        let (sender, receiver) = mpsc::channel::<Packet>();
         s.spawn(|_| {
-            output::run(receiver, &options);
+            output::run(receiver, options, base.unwrap_or_default());
         });
         s.spawn(|_| {
             std::thread::sleep(std::time::Duration::from_secs(1));
             progress.join().unwrap();
-            eprintln!("finished!");
+            events::emit(options.log_format, Event::Info { message: "finished!" });
         });
-    }).unwrap()
+        runtime.block_on(async {
+            let mut ctx = Context::new(client, &progress, options, sender);
+            ctx.base_ids = base_ids;
+            if let Some(count) = options.top {
+                get_top(&mut ctx, options, count).await;
+            } else if let Some(ids) = &watchlist {
+                get_watchlist(&mut ctx, options, ids).await;
+            } else if let Some(window) = options.recent_comments {
+                get_recent_comments(&mut ctx, options, window).await;
+            } else if let (Some(plan), Some(shard)) = (&options.plan, options.shard) {
+                get_periods(&mut ctx, &plan.periods_of(shard.index), &options.fields).await;
+            } else {
+                get_data(&mut ctx, options, base_newest).await;
+            }
+            let epoch = FixedOffset::east(0).timestamp(0, 0);
+            ctx.sender.send(Packet{ since: epoch, until: epoch, last_modified: epoch, videos: Vec::new(), versions: None, total_count: 0, refresh: false }).unwrap();
+            events::emit(options.log_format, Event::Info { message: "finished main thread" });
+            ctx.metrics
+        })
+    }).unwrap();
+
+    if let Some(sample) = &options.sample {
+        if let Some(path) = sample::params_path(options) {
+            sample::write_params(sample, &path, options);
+        }
+    }
+
+    if let Some(shard) = options.shard {
+        if let Some(path) = plan::manifest_path(options) {
+            plan::write_manifest(shard, !is_interrupted() && !is_gave_up(), &path);
+        }
+    }
+
+    let report = metrics.report(is_interrupted(), is_gave_up(), options.sample);
+    events::emit(options.log_format, Event::Report(&report));
+    if let Some(path) = &options.report {
+        create_dir_all(Path::new(path).parent().unwrap()).unwrap();
+        serde_json::to_writer_pretty(File::create(path).unwrap(), &report).unwrap();
+    }
 }
@@ -0,0 +1,172 @@
+use std::time::{Duration, Instant};
+use std::fmt::Write;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use crate::sample::{Sample, SampleSize};
+
+/// counters of requests and periods while crawling
+pub(crate) struct Metrics {
+    started_at: DateTime<Utc>,
+    start: Instant,
+    pub(crate) requests: u64,
+    pub(crate) timeouts: u64,
+    pub(crate) server_errors: u64,
+    pub(crate) unknown_statuses: u64,
+    pub(crate) unknown_errors: u64,
+    pub(crate) version_changes: u64,
+    pub(crate) periods_finished: u64,
+    pub(crate) periods_discarded: u64,
+    pub(crate) periods_mixed: u64,
+    pub(crate) videos: u64,
+    // latencies of succeeded requests
+    latencies: Vec<Duration>,
+}
+
+impl Metrics {
+    pub(crate) fn new() -> Metrics {
+        Metrics {
+            started_at: Utc::now(),
+            start: Instant::now(),
+            requests: 0,
+            timeouts: 0,
+            server_errors: 0,
+            unknown_statuses: 0,
+            unknown_errors: 0,
+            version_changes: 0,
+            periods_finished: 0,
+            periods_discarded: 0,
+            periods_mixed: 0,
+            videos: 0,
+            latencies: Vec::new(),
+        }
+    }
+
+    pub(crate) fn add_latency(&mut self, latency: Duration) {
+        self.latencies.push(latency);
+    }
+
+    /// the mean latency of succeeded requests
+    pub(crate) fn mean_latency(&self) -> Option<Duration> {
+        if self.latencies.is_empty() {
+            None
+        } else {
+            Some(self.latencies.iter().sum::<Duration>() / self.latencies.len() as u32)
+        }
+    }
+
+    pub(crate) fn report(&self, interrupted: bool, gave_up: bool, sample: Option<Sample>) -> Report {
+        let mut latencies = self.latencies.clone();
+        latencies.sort();
+        let millis = |duration: Duration| duration.as_secs_f64() * 1000.0;
+        let percentile = |percent: usize| millis(latencies[(latencies.len() - 1) * percent / 100]);
+        let latency_ms = if latencies.is_empty() {
+            None
+        } else {
+            Some(Latency {
+                min: millis(latencies[0]),
+                mean: millis(latencies.iter().sum::<Duration>()) / latencies.len() as f64,
+                p50: percentile(50),
+                p95: percentile(95),
+                max: millis(latencies[latencies.len() - 1]),
+            })
+        };
+        Report {
+            started_at: self.started_at,
+            finished_at: Utc::now(),
+            elapsed_seconds: self.start.elapsed().as_secs_f64(),
+            interrupted,
+            gave_up,
+            requests: self.requests,
+            succeeded: self.latencies.len() as u64,
+            retries: self.requests - self.latencies.len() as u64,
+            timeouts: self.timeouts,
+            server_errors: self.server_errors,
+            unknown_statuses: self.unknown_statuses,
+            unknown_errors: self.unknown_errors,
+            version_changes: self.version_changes,
+            periods_finished: self.periods_finished,
+            periods_discarded: self.periods_discarded,
+            periods_mixed: self.periods_mixed,
+            videos: self.videos,
+            latency_ms,
+            sample,
+        }
+    }
+}
+
+/// the summary of a crawl written at exit
+#[derive(Serialize)]
+pub(crate) struct Report {
+    started_at: DateTime<Utc>,
+    finished_at: DateTime<Utc>,
+    elapsed_seconds: f64,
+    interrupted: bool,
+    gave_up: bool,
+    requests: u64,
+    succeeded: u64,
+    retries: u64,
+    timeouts: u64,
+    server_errors: u64,
+    unknown_statuses: u64,
+    unknown_errors: u64,
+    version_changes: u64,
+    periods_finished: u64,
+    periods_discarded: u64,
+    // periods with videos got under different versions
+    periods_mixed: u64,
+    videos: u64,
+    // None if no request succeeded
+    latency_ms: Option<Latency>,
+    // the size and the seed to choose the pages. None unless sampling
+    sample: Option<Sample>,
+}
+
+#[derive(Serialize)]
+struct Latency {
+    min: f64,
+    mean: f64,
+    p50: f64,
+    p95: f64,
+    max: f64,
+}
+
+impl Report {
+    /// the table for human
+    pub(crate) fn table(&self) -> String {
+        let mut table = String::new();
+        let mut row = |name: &str, value: &dyn std::fmt::Display| {
+            writeln!(table, "{:<20}{:>14}", name, value).unwrap();
+        };
+        row("elapsed", &format!("{:.1}s", self.elapsed_seconds));
+        row("interrupted", &self.interrupted);
+        row("gave up", &self.gave_up);
+        row("requests", &self.requests);
+        row("succeeded", &self.succeeded);
+        row("retries", &self.retries);
+        row("  timeouts", &self.timeouts);
+        row("  5xx", &self.server_errors);
+        row("  unknown statuses", &self.unknown_statuses);
+        row("  unknown errors", &self.unknown_errors);
+        row("version changes", &self.version_changes);
+        row("periods finished", &self.periods_finished);
+        row("periods discarded", &self.periods_discarded);
+        row("periods mixed", &self.periods_mixed);
+        row("videos", &self.videos);
+        if let Some(latency) = &self.latency_ms {
+            row("latency min", &format!("{:.0}ms", latency.min));
+            row("latency mean", &format!("{:.0}ms", latency.mean));
+            row("latency p50", &format!("{:.0}ms", latency.p50));
+            row("latency p95", &format!("{:.0}ms", latency.p95));
+            row("latency max", &format!("{:.0}ms", latency.max));
+        }
+        if let Some(sample) = &self.sample {
+            match sample.size {
+                SampleSize::Pages(pages) => row("sample pages", &pages),
+                SampleSize::Fraction(fraction) => row("sample fraction", &fraction),
+            }
+            row("sample seed", &sample.seed);
+        }
+        table.pop();
+        table
+    }
+}
@@ -1,6 +1,16 @@
 use clap::*;
-use chrono::{FixedOffset, TimeZone, NaiveDate, Duration, DateTime};
+use chrono::{FixedOffset, Duration, DateTime};
+use structs::date_arg::{parse_timezone, parse_date_time, parse_relative};
+use structs::events::LogFormat;
 use std::process::exit;
+use nico_snapshot_api::FieldName;
+use crate::retry::RetryPolicy;
+use crate::sample::{Sample, SampleSize};
+use crate::config::{read_config, Settings, duration_value};
+use crate::plan::{read_plan, Plan, Shard};
+use toml::value::{Table, Value};
+
+const DEFAULT_USER_AGENT: &str = concat!("view-counter-times-video-length-ranking-getting-daemon/", env!("CARGO_PKG_VERSION"));
 
 macro_rules! exiting_errf {
     ($($arg:tt)*) => ({
@@ -9,76 +19,833 @@ macro_rules! exiting_errf {
     })
 }
 
-const DATE_FORMAT_WITH_TIME: &str = "%Y/%m/%d";
-
 pub fn parse_options() -> Options {
-    let jst_timezone = FixedOffset::east(9 * 3600);
-    let app = app_from_crate!()
+    options_from(&app().get_matches())
+}
+
+/// the options of the command line
+fn app() -> App<'static, 'static> {
+    app_from_crate!()
         .arg(Arg::with_name("since")
-            .help("the begin date of find range. defaults the date starts SMILEVIDEO, 2020/03/06")
+            .help("the begin date of find range. RFC 3339, yyyy/mm/dd [HH:MM[:SS]] in --timezone, \
+                   or relative to now like -7d. defaults 2007/03/01 00:00 +09:00, before SMILEVIDEO started")
             .takes_value(true)
+            .allow_hyphen_values(true)
             .short("-s")
             .long("--since"))
         .arg(Arg::with_name("until")
-            .help("the last date of find range. defaults now")
+            .help("the last date of find range in the same format as since. defaults now")
             .takes_value(true)
+            .allow_hyphen_values(true)
             .short("-u")
             .long("--until"))
+        .arg(Arg::with_name("timezone")
+            .help("timezone of since, until and periods. UTC or offset like +09:00. defaults +09:00")
+            .takes_value(true)
+            .allow_hyphen_values(true)
+            .long("--timezone"))
         .arg(Arg::with_name("duration")
             .help("duration to be got at a time. defaults 1 week")
             .takes_value(true)
             .short("-d")
             .long("--duration"))
-        .arg(Arg::with_name("out-to")
+        .arg(Arg::with_name("query")
+            .help("keyword to search videos. all videos are got if not specified")
+            .takes_value(true)
+            .short("-q")
+            .long("--query"))
+        .arg(Arg::with_name("targets")
+            .help("comma separated fields to search --query in. title, description, tags or tagsExact. \
+                   defaults title,description,tags")
+            .takes_value(true)
+            .long("--targets")
+            .requires("query"))
+        .arg(Arg::with_name("sample-pages")
+            .help("gets the number of randomly chosen pages of 100 videos for each period instead of all videos")
+            .takes_value(true)
+            .long("--sample-pages")
+            .conflicts_with_all(&["sample-fraction", "base"]))
+        .arg(Arg::with_name("sample-fraction")
+            .help("gets the fraction of randomly chosen pages of 100 videos for each period instead of all videos")
+            .takes_value(true)
+            .long("--sample-fraction")
+            .conflicts_with("base"))
+        .arg(Arg::with_name("sample-seed")
+            .help("seed to choose pages for sample. defaults random")
+            .takes_value(true)
+            .long("--sample-seed"))
+        .arg(Arg::with_name("out")
             .help("file to write to. defaults stdout")
             .takes_value(true)
             .short("-o")
             .long("--out"))
-        .arg(Arg::with_name("contents-id-out")
+        .arg(Arg::with_name("out-dir")
+            .help("directory to write a file for each period and index.json listing those files")
+            .takes_value(true)
+            .long("--out-dir")
+            .conflicts_with("out"))
+        .arg(Arg::with_name("index")
+            .help("file to write the index of --out-dir to. defaults index.json in --out-dir")
+            .takes_value(true)
+            .long("--index")
+            .requires("out-dir"))
+        .arg(Arg::with_name("out-format")
+            .help("format of output. sqlite requires --out. defaults bin")
+            .possible_values(&["bin", "sqlite", "jsonl", "csv", "parquet"])
+            .takes_value(true)
+            .long("--out-format"))
+        .arg(Arg::with_name("compress")
+            .help("compress bin output with zstd")
+            .long("--compress"))
+        .arg(Arg::with_name("fields")
+            .help("comma separated fields to be got. contentId and startTime are always got. defaults all fields")
+            .takes_value(true)
+            .long("--fields"))
+        .arg(Arg::with_name("tag-separator")
+            .help("separator of tags in csv output. defaults a space")
+            .takes_value(true)
+            .long("--tag-separator"))
+        .arg(Arg::with_name("content-id-out")
             .help("file to write contents id proceed.")
             .takes_value(true)
             .short("-c")
             .long("--content-id-out"))
-        ;
-    let matches = app.get_matches();
-
-    let since = matches.value_of("since")
-        .map(|date| jst_timezone
-            .from_local_date(&NaiveDate::parse_from_str(date, DATE_FORMAT_WITH_TIME)
-                .unwrap_or_else(|err| exiting_errf!("since: {}", err)))
-            .unwrap()
-            .and_hms(0, 0, 0))
-        .unwrap_or_else(|| jst_timezone.ymd(2007, 03, 01).and_hms(0, 0, 0));
-
-    let until = matches.value_of("until")
-        .map(|date| jst_timezone
-            .from_local_date(&NaiveDate::parse_from_str(date, DATE_FORMAT_WITH_TIME)
-                .unwrap_or_else(|err| exiting_errf!("until: {}", err)))
-            .unwrap()
-            .and_hms(0, 0, 0));
-
-    let duration = matches.value_of("duration")
-        .map(|duration| Duration::from_std(parse_duration::parse(duration)
+        .arg(Arg::with_name("base")
+            .help("previous output bin to be updated. \
+                   videos newer than the newest one in it are got and the others are refreshed")
+            .takes_value(true)
+            .short("-b")
+            .long("--base"))
+        .arg(Arg::with_name("refresh-since")
+            .help("with --base, refreshes only the videos started since this date in the same format as since. \
+                   the older videos are kept as they are in the base. defaults --since")
+            .takes_value(true)
+            .allow_hyphen_values(true)
+            .long("--refresh-since"))
+        .arg(Arg::with_name("convert")
+            .help("output bin to be written in --out-format without getting videos from server")
+            .takes_value(true)
+            .long("--convert")
+            .conflicts_with("base"))
+        .arg(Arg::with_name("timeout")
+            .help("timeout of a request. defaults 10 seconds")
+            .takes_value(true)
+            .long("--timeout"))
+        .arg(Arg::with_name("retry-initial-wait")
+            .help("wait before the first retry. doubled for each failure with jitter. defaults 10 seconds")
+            .takes_value(true)
+            .long("--retry-initial-wait"))
+        .arg(Arg::with_name("retry-max-wait")
+            .help("maximum wait before retrying unless Retry-After is longer. defaults 5 minutes")
+            .takes_value(true)
+            .long("--retry-max-wait"))
+        .arg(Arg::with_name("max-attempts")
+            .help("attempts of a request before giving up. defaults unlimited")
+            .takes_value(true)
+            .long("--max-attempts"))
+        .arg(Arg::with_name("retry-deadline")
+            .help("time since the first attempt of a request to give up retrying. defaults unlimited")
+            .takes_value(true)
+            .long("--retry-deadline"))
+        .arg(Arg::with_name("watch")
+            .help("waits for new snapshots and gets videos for each snapshot. requires --out containing {snapshot}")
+            .long("--watch"))
+        .arg(Arg::with_name("watch-interval")
+            .help("interval to check the snapshot version in watch mode. defaults 10 minutes")
+            .takes_value(true)
+            .long("--watch-interval"))
+        .arg(Arg::with_name("incremental")
+            .help("in watch mode, updates the output for the previous snapshot instead of getting all videos")
+            .long("--incremental")
+            .requires("watch"))
+        .arg(Arg::with_name("then")
+            .help("shell command to be run after getting videos. \
+                   SNAPSHOT_OUT and SNAPSHOT_LAST_MODIFIED environment variables are set")
+            .takes_value(true)
+            .long("--then"))
+        .arg(Arg::with_name("report")
+            .help("file to write the summary of requests in json at exit")
+            .takes_value(true)
+            .long("--report"))
+        .arg(Arg::with_name("validation-report")
+            .help("file to write duplicates, shortfalls and periods should be refetched in json")
+            .takes_value(true)
+            .long("--validation-report"))
+        .arg(Arg::with_name("user-agent")
+            .help("User-Agent of requests. defaults the name and version of this tool")
+            .takes_value(true)
+            .long("--user-agent"))
+        .arg(Arg::with_name("contact")
+            .help("contact information like mail address appended to User-Agent")
+            .takes_value(true)
+            .long("--contact"))
+        .arg(Arg::with_name("proxy")
+            .help("url of the proxy for requests")
+            .takes_value(true)
+            .long("--proxy"))
+        .arg(Arg::with_name("request-interval")
+            .help("minimum wait between requests. the time taken by the last request is waited if longer. \
+                   defaults 10 milliseconds")
+            .takes_value(true)
+            .long("--request-interval"))
+        .arg(Arg::with_name("keep-mixed-versions")
+            .help("keeps getting the period if the snapshot version is changed while getting it \
+                   instead of getting it again. each video is written with the version it is got under. \
+                   also merges the shards got under different versions with --merge-shards")
+            .long("--keep-mixed-versions"))
+        .arg(Arg::with_name("config")
+            .help("toml file with the long names of options as keys. \
+                   overridden by environment variables like NICO_OUT_FORMAT and options")
+            .takes_value(true)
+            .long("--config"))
+        .arg(Arg::with_name("verify")
+            .help("bin to be verified. randomly chosen periods are got again and compared with it. \
+                   the result is written to --out in json")
+            .takes_value(true)
+            .long("--verify")
+            .conflicts_with_all(&["base", "convert", "watch", "dry-run", "sample-pages", "sample-fraction"]))
+        .arg(Arg::with_name("verify-periods")
+            .help("count of periods to be verified. defaults 10")
+            .takes_value(true)
+            .long("--verify-periods"))
+        .arg(Arg::with_name("top")
+            .help("gets only the top videos by watch sum using the lists sorted on server. \
+                   the videos are got until the top is guaranteed to be same as the top of all videos")
+            .takes_value(true)
+            .long("--top")
+            .conflicts_with_all(&["base", "convert", "watch", "dry-run", "verify", "sample-pages", "sample-fraction"]))
+        .arg(Arg::with_name("watchlist")
+            .help("file of content ids, one per line like --content-id-out writes. \
+                   gets only those videos and appends them to --out. requires jsonl or csv --out-format")
+            .takes_value(true)
+            .long("--watchlist")
+            .conflicts_with_all(&["base", "convert", "out-dir", "dry-run", "verify", "top",
+                                  "sample-pages", "sample-fraction"]))
+        .arg(Arg::with_name("recent-comments")
+            .help("gets only the videos commented in the duration until now like 1day, \
+                   merged into --base if specified. --duration is used as the periods of comment time")
+            .takes_value(true)
+            .long("--recent-comments")
+            .conflicts_with_all(&["convert", "dry-run", "verify", "top", "watchlist"]))
+        .arg(Arg::with_name("plan-out")
+            .help("writes the plan of the crawl split into --shards to the file and exits")
+            .takes_value(true)
+            .long("--plan-out")
+            .conflicts_with("plan"))
+        .arg(Arg::with_name("shards")
+            .help("count of shards in the plan. defaults 1")
+            .takes_value(true)
+            .long("--shards")
+            .requires("plan-out"))
+        .arg(Arg::with_name("plan")
+            .help("plan written by --plan-out. used with --shard or --merge-shards")
+            .takes_value(true)
+            .long("--plan")
+            .conflicts_with_all(&["since", "until", "duration", "query", "targets"]))
+        .arg(Arg::with_name("shard")
+            .help("index of the shard in --plan to be got. the manifest is written next to --out")
+            .takes_value(true)
+            .long("--shard")
+            .requires("plan")
+            .conflicts_with_all(&["base", "convert", "watch", "verify", "top", "watchlist", "recent-comments"]))
+        .arg(Arg::with_name("merge-shards")
+            .help("comma separated outputs of all shards in --plan to be merged into --out. \
+                   fails if some shards are missing or got under different snapshot versions")
+            .takes_value(true)
+            .long("--merge-shards")
+            .requires("plan")
+            .conflicts_with_all(&["shard", "base", "convert", "watch", "verify", "top", "watchlist",
+                                  "recent-comments", "dry-run"]))
+        .arg(Arg::with_name("raw-dir")
+            .help("directory to store the raw json pages compressed with zstd for --rebuild-from-raw")
+            .takes_value(true)
+            .long("--raw-dir")
+            .conflicts_with_all(&["convert", "dry-run", "verify", "top", "watchlist", "merge-shards"]))
+        .arg(Arg::with_name("rebuild-from-raw")
+            .help("writes the videos in the raw pages stored by --raw-dir to the output without crawling")
+            .takes_value(true)
+            .long("--rebuild-from-raw")
+            .conflicts_with_all(&["raw-dir", "convert", "dry-run", "verify", "top", "watchlist", "recent-comments",
+                                  "watch", "plan", "sample-pages", "sample-fraction"]))
+        .arg(Arg::with_name("dry-run")
+            .help("prints the count of videos for each period and the estimate of the crawl without getting videos")
+            .long("--dry-run")
+            .conflicts_with_all(&["watch", "convert"]))
+        .arg(Arg::with_name("print-config")
+            .help("prints the effective configuration in toml and exits")
+            .long("--print-config"))
+        .arg(Arg::with_name("log-format")
+            .help("format of log to stderr. json writes a json object per line instead of progress bars")
+            .possible_values(&["text", "json"])
+            .takes_value(true)
+            .long("--log-format"))
+}
+
+/// the options given by the arguments. the first one is the name of the command
+#[cfg(test)]
+pub(crate) fn options_of(args: &[&str]) -> Options {
+    options_from(&app().get_matches_from(args))
+}
+
+/// the options given by the command line, the environment variables and the config file
+fn options_from(matches: &ArgMatches) -> Options {
+    let config = matches.value_of("config").map(|x| x.to_owned())
+        .or_else(|| std::env::var("NICO_CONFIG").ok())
+        .map(|path| read_config(&path).unwrap_or_else(|err| exiting_errf!("config: {}: {}", path, err)))
+        .unwrap_or_default();
+    let settings = Settings::new(matches, config);
+
+    let timezone = settings.value_of("timezone")
+        .map(|timezone| parse_timezone(&timezone)
+            .unwrap_or_else(|err| exiting_errf!("timezone: {}", err)))
+        .unwrap_or_else(|| FixedOffset::east_opt(9 * 3600).unwrap());
+
+    let plan_path = settings.value_of("plan").map(|x| x.into_owned());
+    let plan = plan_path.as_ref()
+        .map(|path| read_plan(path).unwrap_or_else(|err| exiting_errf!("plan: {}: {}", path, err)));
+    // checked here too since clap doesn't know values from config file and environment variables
+    if plan.is_some() && ["since", "until", "duration", "query", "targets"].iter()
+        .any(|&name| settings.value_of(name).is_some()) {
+        exiting_errf!("plan: cannot be used with --since, --until, --duration, --query or --targets")
+    }
+
+    let mut since = settings.value_of("since")
+        .map(|date| parse_date_time(&date, &timezone)
+            .unwrap_or_else(|err| exiting_errf!("since: {}", err)))
+        .unwrap_or_else(|| DateTime::parse_from_rfc3339("2007-03-01T00:00:00+09:00").unwrap()
+            .with_timezone(&timezone));
+
+    let mut until = settings.value_of("until")
+        .map(|date| parse_date_time(&date, &timezone)
+            .unwrap_or_else(|err| exiting_errf!("until: {}", err)));
+    // relative dates are resolved again for each snapshot in watch mode
+    let since_ago = settings.value_of("since").and_then(|date| parse_relative(&date)?.ok());
+    let until_ago = settings.value_of("until").and_then(|date| parse_relative(&date)?.ok());
+    let since_arg = settings.value_of("since").map(|x| x.into_owned());
+    let until_arg = settings.value_of("until").map(|x| x.into_owned());
+
+    let mut duration = settings.value_of("duration")
+        .map(|duration| Duration::from_std(parse_duration::parse(&duration)
             .unwrap_or_else(|err| exiting_errf!("duration: {}", err))).unwrap())
         .unwrap_or_else(|| Duration::weeks(1));
+    // the periods never proceed if shorter
+    if duration < Duration::seconds(1) {
+        exiting_errf!("duration: must be 1 second or longer")
+    }
+
+    let mut query = settings.value_of("query").map_or_else(String::new, |x| x.into_owned());
+
+    let mut targets = match settings.value_of("targets") {
+        None if query.is_empty() => Vec::new(),
+        None => DEFAULT_TARGETS.iter().map(|&x| x.to_owned()).collect(),
+        Some(_) if query.is_empty() => exiting_errf!("targets: requires --query"),
+        Some(targets) => targets.split(',')
+            .map(|target| target.trim())
+            .map(|target| if TARGETS.contains(&target) {
+                target.to_owned()
+            } else {
+                exiting_errf!("targets: unknown target: {}", target)
+            })
+            .collect(),
+    };
+
+    let shard = match (&plan, settings.value_of("shard")) {
+        (_, None) => None,
+        (None, Some(_)) => exiting_errf!("shard: requires --plan"),
+        (Some(plan), Some(index)) => {
+            let index = index.parse::<usize>().unwrap_or_else(|err| exiting_errf!("shard: {}", err));
+            let planned = plan.shards.get(index)
+                .unwrap_or_else(|| exiting_errf!("shard: the plan has {} shards", plan.shards.len()));
+            // the shard gets the periods in the plan. those are not computed again from the range
+            since = planned.since;
+            until = Some(planned.until);
+            duration = Duration::seconds(plan.duration_seconds);
+            query = plan.query.clone();
+            targets = plan.targets.clone();
+            Some(Shard { plan_id: plan.id, index })
+        }
+    };
+
+    let sample_size = match (settings.value_of("sample-pages"), settings.value_of("sample-fraction")) {
+        (None, None) => None,
+        (Some(_), Some(_)) => exiting_errf!("sample-pages: cannot be used with --sample-fraction"),
+        (Some(pages), None) => Some(SampleSize::Pages(pages.parse()
+            .unwrap_or_else(|err| exiting_errf!("sample-pages: {}", err)))),
+        (None, Some(fraction)) => match fraction.parse::<f64>() {
+            Ok(fraction) if 0.0 < fraction && fraction <= 1.0 => Some(SampleSize::Fraction(fraction)),
+            Ok(fraction) => exiting_errf!("sample-fraction: must be in (0, 1] but was {}", fraction),
+            Err(err) => exiting_errf!("sample-fraction: {}", err),
+        },
+    };
+    let sample_seed = settings.value_of("sample-seed")
+        .map(|seed| seed.parse()
+            .unwrap_or_else(|err| exiting_errf!("sample-seed: {}", err)));
+    let sample = sample_size.map(|size| Sample {
+        size,
+        seed: sample_seed.unwrap_or_else(rand::random),
+    });
+
+    let out = settings.value_of("out").map(|x| x.into_owned());
+
+    let out_dir = settings.value_of("out-dir").map(|x| x.into_owned());
+    if out.is_some() && out_dir.is_some() {
+        exiting_errf!("out-dir: cannot be used with --out")
+    }
+    let index = settings.value_of("index").map(|x| x.into_owned());
+    if index.is_some() && out_dir.is_none() {
+        exiting_errf!("index: requires --out-dir")
+    }
+
+    let out_format = match settings.value_of("out-format").as_deref() {
+        Some("sqlite") => OutFormat::Sqlite,
+        Some("jsonl") => OutFormat::Jsonl,
+        Some("csv") => OutFormat::Csv,
+        Some("parquet") => OutFormat::Parquet,
+        Some("bin") | None => OutFormat::Bin,
+        Some(other) => exiting_errf!("out-format: unknown format: {}", other),
+    };
+    if out_format == OutFormat::Sqlite && out.is_none() {
+        exiting_errf!("out-format: sqlite requires --out")
+    }
+    if out_format == OutFormat::Sqlite && out_dir.is_some() {
+        exiting_errf!("out-format: sqlite cannot be written to --out-dir")
+    }
 
-    let out = matches.value_of("out-to").map(|x| x.to_owned());
+    let compress = settings.is_present("compress");
+    if compress && out_format != OutFormat::Bin {
+        exiting_errf!("compress: only bin output can be compressed")
+    }
+
+    let fields = match settings.value_of("fields") {
+        None => FieldName::all_values().to_vec(),
+        Some(listed) => {
+            let mut fields = Vec::new();
+            let listed = listed.split(',')
+                .map(|field| field.trim().parse::<FieldName>()
+                    .unwrap_or_else(|err| exiting_errf!("fields: {}", err)));
+            for field in listed.chain(vec![FieldName::ContentId, FieldName::StartTime]) {
+                if !fields.contains(&field) {
+                    fields.push(field);
+                }
+            }
+            fields
+        }
+    };
+
+    let tag_separator = settings.value_of("tag-separator").map_or_else(|| " ".to_owned(), |x| x.into_owned());
+
+    let contents_id_out = settings.value_of("content-id-out").map(|x| x.into_owned());
+
+    let base = settings.value_of("base").map(|x| x.into_owned());
+
+    let convert = settings.value_of("convert").map(|x| x.into_owned());
+
+    let refresh_since = settings.value_of("refresh-since")
+        .map(|date| parse_date_time(&date, &timezone)
+            .unwrap_or_else(|err| exiting_errf!("refresh-since: {}", err)));
+    let refresh_since_ago = settings.value_of("refresh-since").and_then(|date| parse_relative(&date)?.ok());
+    let refresh_since_arg = settings.value_of("refresh-since").map(|x| x.into_owned());
+
+    // checked here too since clap doesn't know values from config file and environment variables
+    if base.is_some() && (convert.is_some() || sample.is_some()) {
+        exiting_errf!("base: cannot be used with --convert or sampling")
+    }
+
+    let std_duration = |name: &str| settings.value_of(name)
+        .map(|duration| parse_duration::parse(&duration)
+            .unwrap_or_else(|err| exiting_errf!("{}: {}", name, err)));
+    let retry = RetryPolicy {
+        timeout: std_duration("timeout").unwrap_or_else(|| std::time::Duration::from_secs(10)),
+        initial_wait: std_duration("retry-initial-wait").unwrap_or_else(|| std::time::Duration::from_secs(10)),
+        max_wait: std_duration("retry-max-wait").unwrap_or_else(|| std::time::Duration::from_secs(5 * 60)),
+        max_attempts: settings.value_of("max-attempts")
+            .map(|attempts| attempts.parse()
+                .unwrap_or_else(|err| exiting_errf!("max-attempts: {}", err))),
+        deadline: std_duration("retry-deadline"),
+    };
+
+    let user_agent = settings.value_of("user-agent").map_or_else(|| DEFAULT_USER_AGENT.to_owned(), |x| x.into_owned());
+    let user_agent = match settings.value_of("contact") {
+        Some(contact) => format!("{} ({})", user_agent, contact),
+        None => user_agent,
+    };
+
+    let proxy = settings.value_of("proxy").map(|x| x.into_owned());
+    if let Some(proxy) = &proxy {
+        url::Url::parse(proxy).unwrap_or_else(|err| exiting_errf!("proxy: {}", err));
+    }
+
+    let request_interval = std_duration("request-interval").unwrap_or_else(|| std::time::Duration::from_millis(10));
+
+    let keep_mixed_versions = settings.is_present("keep-mixed-versions");
+
+    let watch = settings.is_present("watch");
+    let watch_interval = std_duration("watch-interval").unwrap_or_else(|| std::time::Duration::from_secs(10 * 60));
+    let incremental = settings.is_present("incremental");
+    if incremental && !watch {
+        exiting_errf!("incremental: requires --watch")
+    }
+    if watch && out_dir.is_none() && !out.as_deref().unwrap_or("").contains(SNAPSHOT_PLACEHOLDER) {
+        exiting_errf!("watch: --out must contain {} or --out-dir is required", SNAPSHOT_PLACEHOLDER)
+    }
+    if incremental && (out.is_none() || out_format != OutFormat::Bin) {
+        exiting_errf!("incremental: only bin output to --out can be updated")
+    }
+    if refresh_since.is_some() && base.is_none() && !incremental {
+        exiting_errf!("refresh-since: requires --base or --incremental")
+    }
+
+    let then = settings.value_of("then").map(|x| x.into_owned());
+
+    let report = settings.value_of("report").map(|x| x.into_owned());
 
-    let contents_id_out = matches.value_of("contents-id-out").map(|x| x.to_owned());
+    let validation_report = settings.value_of("validation-report").map(|x| x.into_owned());
+
+    let log_format = match settings.value_of("log-format").as_deref() {
+        Some("json") => LogFormat::Json,
+        Some("text") | None => LogFormat::Text,
+        Some(other) => exiting_errf!("log-format: unknown format: {}", other),
+    };
+    // every line of stderr is json even if panicked
+    structs::events::report_panics(log_format);
+
+    let verify = settings.value_of("verify").map(|x| x.into_owned());
+    if verify.is_some() && (base.is_some() || convert.is_some() || watch || sample.is_some()) {
+        exiting_errf!("verify: cannot be used with --base, --convert, --watch or sampling")
+    }
+    let verify_periods = settings.value_of("verify-periods")
+        .map_or(10, |periods| periods.parse()
+            .unwrap_or_else(|err| exiting_errf!("verify-periods: {}", err)));
+
+    let top = settings.value_of("top")
+        .map(|count| count.parse()
+            .unwrap_or_else(|err| exiting_errf!("top: {}", err)));
+    if top.is_some() && (base.is_some() || convert.is_some() || watch || verify.is_some() || sample.is_some()) {
+        exiting_errf!("top: cannot be used with --base, --convert, --watch, --verify or sampling")
+    }
+
+    let watchlist = settings.value_of("watchlist").map(|x| x.into_owned());
+    if watchlist.is_some() && (base.is_some() || convert.is_some() || out_dir.is_some() || verify.is_some()
+        || top.is_some() || sample.is_some()) {
+        exiting_errf!("watchlist: cannot be used with --base, --convert, --out-dir, --verify, --top or sampling")
+    }
+    if watchlist.is_some() && out_format != OutFormat::Jsonl && out_format != OutFormat::Csv {
+        exiting_errf!("watchlist: only jsonl or csv output can be appended")
+    }
+
+    let recent_comments = std_duration("recent-comments");
+    if recent_comments.is_some() && (convert.is_some() || verify.is_some() || top.is_some() || watchlist.is_some()) {
+        exiting_errf!("recent-comments: cannot be used with --convert, --verify, --top or --watchlist")
+    }
+
+    let plan_out = settings.value_of("plan-out").map(|x| x.into_owned());
+    let shards = settings.value_of("shards")
+        .map_or(1, |shards| shards.parse()
+            .unwrap_or_else(|err| exiting_errf!("shards: {}", err)));
+
+    let merge_shards = settings.value_of("merge-shards")
+        .map_or_else(Vec::new, |paths| paths.split(',').map(|path| path.trim().to_owned()).collect());
+    if plan.is_some() && shard.is_none() && merge_shards.is_empty() {
+        exiting_errf!("plan: requires --shard or --merge-shards")
+    }
+    if !merge_shards.is_empty() && plan.is_none() {
+        exiting_errf!("merge-shards: requires --plan")
+    }
+    if !merge_shards.is_empty() && out_dir.is_some() {
+        exiting_errf!("merge-shards: cannot be used with --out-dir")
+    }
+    if shard.is_some() && (base.is_some() || convert.is_some() || watch || verify.is_some() || top.is_some()
+        || watchlist.is_some() || recent_comments.is_some()) {
+        exiting_errf!("shard: cannot be used with --base, --convert, --watch, --verify, --top, --watchlist \
+                       or --recent-comments")
+    }
+    if shard.is_some() && (out.is_none() || out_format != OutFormat::Bin) {
+        exiting_errf!("shard: only bin output to --out can be merged")
+    }
+
+    let raw_dir = settings.value_of("raw-dir").map(|x| x.into_owned());
+    if raw_dir.is_some() && (convert.is_some() || verify.is_some() || top.is_some() || watchlist.is_some()
+        || !merge_shards.is_empty()) {
+        exiting_errf!("raw-dir: cannot be used with --convert, --verify, --top, --watchlist or --merge-shards")
+    }
+    let rebuild_from_raw = settings.value_of("rebuild-from-raw").map(|x| x.into_owned());
+    if rebuild_from_raw.is_some() && (raw_dir.is_some() || convert.is_some() || verify.is_some() || top.is_some()
+        || watchlist.is_some() || recent_comments.is_some() || watch || plan.is_some() || sample.is_some()) {
+        exiting_errf!("rebuild-from-raw: cannot be used with --raw-dir, --convert, --verify, --top, --watchlist, \
+                       --recent-comments, --watch, --plan or sampling")
+    }
+
+    let dry_run = settings.is_present("dry-run");
+    if dry_run && (watch || convert.is_some() || verify.is_some() || top.is_some() || watchlist.is_some()
+        || recent_comments.is_some()) {
+        exiting_errf!("dry-run: cannot be used with --watch, --convert, --verify, --top, --watchlist or --recent-comments")
+    }
+
+    let print_config = matches.is_present("print-config");
+
+    let unknown_keys = settings.unknown_keys();
+    if !unknown_keys.is_empty() {
+        exiting_errf!("config: unknown keys: {}", unknown_keys.join(", "))
+    }
 
     Options {
         since,
         until,
+        since_ago,
+        until_ago,
+        since_arg,
+        until_arg,
         duration,
+        query,
+        targets,
+        sample,
         out,
-        contents_id_out
+        out_dir,
+        index,
+        out_format,
+        compress,
+        fields,
+        tag_separator,
+        contents_id_out,
+        base,
+        refresh_since,
+        refresh_since_ago,
+        refresh_since_arg,
+        convert,
+        retry,
+        user_agent,
+        proxy,
+        request_interval,
+        keep_mixed_versions,
+        watch,
+        watch_interval,
+        incremental,
+        then,
+        report,
+        validation_report,
+        log_format,
+        verify,
+        verify_periods,
+        top,
+        watchlist,
+        recent_comments,
+        plan_path,
+        plan,
+        shard,
+        plan_out,
+        shards,
+        merge_shards,
+        raw_dir,
+        rebuild_from_raw,
+        dry_run,
+        print_config,
     }
 }
 
+/// the fields can be searched by --query
+const TARGETS: &[&str] = &["title", "description", "tags", "tagsExact"];
+const DEFAULT_TARGETS: &[&str] = &["title", "description", "tags"];
+
+/// replaced with the date of the snapshot in --out, --out-dir, --raw-dir, --report and --validation-report
+pub const SNAPSHOT_PLACEHOLDER: &str = "{snapshot}";
+
+/// the format of dates in the names of files
+pub const SNAPSHOT_NAME_FORMAT: &str = "%Y%m%d-%H%M";
+
+#[derive(Clone)]
 pub struct Options {
     pub since: DateTime<FixedOffset>,
     pub until: Option<DateTime<FixedOffset>>,
+    // Some if --since or --until is relative to now
+    pub since_ago: Option<Duration>,
+    pub until_ago: Option<Duration>,
+    // as given to be written by --print-config so that relative ones are kept relative
+    pub since_arg: Option<String>,
+    pub until_arg: Option<String>,
     pub duration: Duration,
+    // empty to get all videos
+    pub query: String,
+    pub targets: Vec<String>,
+    // None to get all videos
+    pub sample: Option<Sample>,
     pub out: Option<String>,
+    pub out_dir: Option<String>,
+    pub index: Option<String>,
+    pub out_format: OutFormat,
+    pub compress: bool,
+    pub fields: Vec<FieldName>,
+    pub tag_separator: String,
     pub contents_id_out: Option<String>,
+    pub base: Option<String>,
+    // the videos older than this in the base are not refreshed. None to refresh since --since
+    pub refresh_since: Option<DateTime<FixedOffset>>,
+    pub refresh_since_ago: Option<Duration>,
+    pub refresh_since_arg: Option<String>,
+    pub convert: Option<String>,
+    pub retry: RetryPolicy,
+    pub user_agent: String,
+    pub proxy: Option<String>,
+    // the minimum wait between requests. the time taken by the last request is waited if longer
+    pub request_interval: std::time::Duration,
+    // true to keep videos got before the snapshot version is changed in a period
+    pub keep_mixed_versions: bool,
+    pub watch: bool,
+    pub watch_interval: std::time::Duration,
+    pub incremental: bool,
+    pub then: Option<String>,
+    pub report: Option<String>,
+    pub validation_report: Option<String>,
+    pub log_format: LogFormat,
+    pub verify: Option<String>,
+    pub verify_periods: usize,
+    // the count of videos to be got by --top
+    pub top: Option<usize>,
+    // the file of content ids to be got
+    pub watchlist: Option<String>,
+    // the window of comment time to be got
+    pub recent_comments: Option<std::time::Duration>,
+    pub plan_path: Option<String>,
+    pub plan: Option<Plan>,
+    // the shard of the plan to be got. since, until, duration and query are of the shard
+    pub shard: Option<Shard>,
+    pub plan_out: Option<String>,
+    pub shards: usize,
+    pub merge_shards: Vec<String>,
+    pub raw_dir: Option<String>,
+    pub rebuild_from_raw: Option<String>,
+    pub dry_run: bool,
+    pub print_config: bool,
+}
+
+impl Options {
+    /// true if the field is got from server
+    pub fn has_field(&self, field: FieldName) -> bool {
+        self.fields.contains(&field)
+    }
+
+    /// true if the fields are limited by --fields
+    pub fn is_projected(&self) -> bool {
+        !FieldName::all_values().iter().all(|&field| self.has_field(field))
+    }
+
+    /// true if the output is appended to the existing file instead of replaced
+    pub fn appends_output(&self) -> bool {
+        self.watchlist.is_some()
+    }
+
+    /// the effective configuration which can be used as the config file
+    pub fn to_config(&self) -> Table {
+        fn string(value: impl ToString) -> Value {
+            Value::String(value.to_string())
+        }
+        let mut config = Table::new();
+        let mut put = |name: &str, value: Option<Value>| {
+            if let Some(value) = value {
+                config.insert(name.to_owned(), value);
+            }
+        };
+        put("timezone", Some(string(self.since.timezone())));
+        // those are read from the plan
+        if self.plan.is_none() {
+            put("since", Some(string(self.since_arg.clone().unwrap_or_else(|| self.since.to_rfc3339()))));
+            put("until", self.until_arg.as_ref().map(string));
+            put("duration", self.duration.to_std().ok().map(duration_value));
+        }
+        if !self.query.is_empty() && self.plan.is_none() {
+            put("query", Some(string(&self.query)));
+            put("targets", Some(Value::Array(self.targets.iter().map(string).collect())));
+        }
+        if let Some(sample) = &self.sample {
+            match sample.size {
+                SampleSize::Pages(pages) => put("sample-pages", Some(Value::Integer(pages.into()))),
+                SampleSize::Fraction(fraction) => put("sample-fraction", Some(Value::Float(fraction))),
+            }
+            put("sample-seed", Some(string(sample.seed)));
+        }
+        put("out", self.out.as_ref().map(string));
+        put("out-dir", self.out_dir.as_ref().map(string));
+        put("index", self.index.as_ref().map(string));
+        put("out-format", Some(string(match self.out_format {
+            OutFormat::Bin => "bin",
+            OutFormat::Sqlite => "sqlite",
+            OutFormat::Jsonl => "jsonl",
+            OutFormat::Csv => "csv",
+            OutFormat::Parquet => "parquet",
+        })));
+        put("compress", Some(Value::Boolean(self.compress)));
+        put("fields", Some(Value::Array(self.fields.iter().map(string).collect())));
+        put("tag-separator", Some(string(&self.tag_separator)));
+        put("content-id-out", self.contents_id_out.as_ref().map(string));
+        put("base", self.base.as_ref().map(string));
+        put("refresh-since", self.refresh_since_arg.as_ref().map(string));
+        put("convert", self.convert.as_ref().map(string));
+        put("timeout", Some(duration_value(self.retry.timeout)));
+        put("retry-initial-wait", Some(duration_value(self.retry.initial_wait)));
+        put("retry-max-wait", Some(duration_value(self.retry.max_wait)));
+        put("max-attempts", self.retry.max_attempts.map(|attempts| Value::Integer(attempts.into())));
+        put("retry-deadline", self.retry.deadline.map(duration_value));
+        put("user-agent", Some(string(&self.user_agent)));
+        put("proxy", self.proxy.as_ref().map(string));
+        put("request-interval", Some(duration_value(self.request_interval)));
+        put("keep-mixed-versions", Some(Value::Boolean(self.keep_mixed_versions)));
+        put("watch", Some(Value::Boolean(self.watch)));
+        put("watch-interval", Some(duration_value(self.watch_interval)));
+        put("incremental", Some(Value::Boolean(self.incremental)));
+        put("then", self.then.as_ref().map(string));
+        put("report", self.report.as_ref().map(string));
+        put("validation-report", self.validation_report.as_ref().map(string));
+        put("verify", self.verify.as_ref().map(string));
+        put("verify-periods", Some(Value::Integer(self.verify_periods as i64)));
+        put("top", self.top.map(|count| Value::Integer(count as i64)));
+        put("watchlist", self.watchlist.as_ref().map(string));
+        put("recent-comments", self.recent_comments.map(duration_value));
+        put("plan", self.plan_path.as_ref().map(string));
+        put("shard", self.shard.map(|shard| Value::Integer(shard.index as i64)));
+        put("plan-out", self.plan_out.as_ref().map(string));
+        if self.plan_out.is_some() {
+            put("shards", Some(Value::Integer(self.shards as i64)));
+        }
+        put("raw-dir", self.raw_dir.as_ref().map(string));
+        put("rebuild-from-raw", self.rebuild_from_raw.as_ref().map(string));
+        put("merge-shards", if self.merge_shards.is_empty() {
+            None
+        } else {
+            Some(Value::Array(self.merge_shards.iter().map(string).collect()))
+        });
+        put("dry-run", Some(Value::Boolean(self.dry_run)));
+        put("log-format", Some(string(match self.log_format {
+            LogFormat::Text => "text",
+            LogFormat::Json => "json",
+        })));
+        config
+    }
+}
+
+#[derive(Eq, PartialEq, Copy, Clone)]
+pub enum OutFormat {
+    Bin,
+    Sqlite,
+    Jsonl,
+    Csv,
+    Parquet,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_keeps_relative_dates() {
+        let options = options_of(&["get-nico-data", "--since", "-7d", "--until", "2020/01/01"]);
+        let config = options.to_config();
+        assert_eq!(config["since"].as_str(), Some("-7d"));
+        assert_eq!(config["until"].as_str(), Some("2020/01/01"));
+    }
+
+    #[test]
+    fn config_of_default_since() {
+        let config = options_of(&["get-nico-data"]).to_config();
+        assert_eq!(config["since"].as_str(), Some("2007-03-01T00:00:00+09:00"));
+        assert!(!config.contains_key("until"));
+    }
 }
@@ -1,61 +1,481 @@
+mod sqlite;
+mod export;
+mod parquet;
+mod period_dir;
+
 use crate::Packet;
 use std::io::{Write, BufWriter, stdout, Stdout};
 use std::sync::mpsc::Receiver;
-use structs::NewVideoInfo;
-use crate::options::Options;
-use std::fs::{create_dir_all, File};
+use structs::PartialVideoInfo;
+use structs::bin::{read_bin, write_full, write_partial};
+use crate::options::{Options, OutFormat};
+use std::fs::{create_dir_all, rename, File, OpenOptions};
 use std::path::Path;
 use either::{Either, Left, Right};
-use chrono::Utc;
+use chrono::{DateTime, FixedOffset, Utc};
+use nico_snapshot_api::{FieldName, VideoInfo};
+use std::collections::HashMap;
+use crate::events::{self, Event};
+use crate::validation::Validation;
+use std::collections::HashSet;
 
-pub(crate) fn run(receiver: Receiver<Packet>, options: &Options) {
-    let mut writer: Either<Stdout, File> = match &options.out {
-        None => Left(stdout()),
-        Some(name) => {
-            create_dir_all(Path::new(&name).parent().unwrap()).unwrap();
-            Right(File::create(name).unwrap())
-        }
-    };
-    let writer: &mut dyn Write = match &mut writer {
-        Left(left) => left,
-        Right(right) => right,
-    };
-    let mut writer = BufWriter::new(writer);
+/// where the got videos are written to
+pub(crate) trait Sink {
+    /// called for each period got.
+    /// `videos` may contain the videos written before if those were refreshed.
+    fn write_period(&mut self, period: &Period, videos: &[&PartialVideoInfo]);
+
+    /// called at last with all videos including the ones in base archive.
+    fn finish(self: Box<Self>, videos: &[PartialVideoInfo]);
+}
+
+pub(crate) struct Period {
+    pub(crate) since: DateTime<FixedOffset>,
+    pub(crate) until: DateTime<FixedOffset>,
+    pub(crate) last_modified: DateTime<FixedOffset>,
+}
+
+/// `base` is the previous archive to be updated. videos got are merged to it.
+pub(crate) fn run(receiver: Receiver<Packet>, options: &Options, base: Vec<PartialVideoInfo>) {
+    let mut sink = create_sink(options, &base);
 
     let mut contents_id_out = options.contents_id_out.as_ref().map(|name| {
         create_dir_all(Path::new(&name).parent().unwrap()).unwrap();
         BufWriter::new(File::create(name).unwrap())
     });
 
-    let mut list = Vec::<NewVideoInfo>::new();
+    let mut index_of = base.iter()
+        .enumerate()
+        .map(|(index, video)| (video.content_id.clone(), index))
+        .collect::<HashMap<_, _>>();
+    let mut list = base;
+    let mut validation = Validation::new();
     for packet in receiver.iter() {
         if packet.last_modified.offset().utc_minus_local() == 0 && packet.last_modified.timestamp() == 0 {
             break
         }
-        for video in packet.videos {
+        validation.check(&packet);
+        let mut indices = Vec::with_capacity(packet.videos.len());
+        let mut got = HashSet::with_capacity(packet.videos.len());
+        let versions = (0..packet.videos.len()).map(|index| packet.version_of(index)).collect::<Vec<_>>();
+        for (video, version) in packet.videos.into_iter().zip(versions) {
+            // the later one is used if duplicated in a period
+            let duplicated = !got.insert(video.content_id.clone().unwrap());
             if let Some(out) = &mut contents_id_out {
-                writeln!(out, "{}", video.content_id.as_ref().unwrap()).unwrap();
-                out.flush().unwrap();
+                if !duplicated {
+                    writeln!(out, "{}", video.content_id.as_ref().unwrap()).unwrap();
+                    out.flush().unwrap();
+                }
+            }
+            let mut video = to_partial_video_info(video, version, options);
+            match index_of.get(&video.content_id) {
+                Some(&index) => {
+                    let old = &mut list[index];
+                    if packet.refresh {
+                        // those are not requested to refresh
+                        video.description = old.description.take();
+                        video.thumbnail_url = old.thumbnail_url.take();
+                        video.last_res_body = old.last_res_body.take();
+                    }
+                    *old = video;
+                    if !duplicated {
+                        indices.push(index);
+                    }
+                }
+                None => {
+                    index_of.insert(video.content_id.clone(), list.len());
+                    indices.push(list.len());
+                    list.push(video);
+                }
             }
-            list.push (NewVideoInfo {
-                last_modified: packet.last_modified.with_timezone(&Utc),
-                content_id: video.content_id.unwrap(),
-                title: video.title.unwrap(),
-                description: video.description,
-                view_counter: video.view_counter.unwrap(),
-                mylist_counter: video.mylist_counter.unwrap(),
-                length_seconds: video.length_seconds.unwrap(),
-                thumbnail_url: video.thumbnail_url,
-                start_time: video.start_time.unwrap().with_timezone(&Utc),
-                last_res_body: video.last_res_body,
-                comment_counter: video.comment_counter.unwrap(),
-                last_comment_time: video.last_comment_time.map(|x| x.with_timezone(&Utc)),
-                category_tags: video.category_tags,
-                tags: video.tags.unwrap(),
-                genre: video.genre,
-            });
-        }
-    }
-    eprintln!("writeing.....");
-    bincode::serialize_into(&mut writer, &list).unwrap();
-}
\ No newline at end of file
+        }
+        let period = Period {
+            since: packet.since,
+            until: packet.until,
+            last_modified: packet.last_modified,
+        };
+        sink.write_period(&period, &indices.iter().map(|&index| &list[index]).collect::<Vec<_>>());
+    }
+    events::emit(options.log_format, Event::Info { message: "writeing....." });
+    sink.finish(&list);
+
+    let validation = validation.report();
+    events::emit(options.log_format, Event::Validation(&validation));
+    if let Some(path) = &options.validation_report {
+        create_dir_all(Path::new(path).parent().unwrap()).unwrap();
+        serde_json::to_writer_pretty(File::create(path).unwrap(), &validation).unwrap();
+    }
+}
+
+/// writes the videos in the bin file to the output without crawling.
+/// the fields not in the bin are not written.
+pub(crate) fn convert(options: &Options, path: &str) {
+    let archive = read_archive(path)
+        .unwrap_or_else(|err| events::exit_with_error(options.log_format, &format!("convert: {}", err)));
+    let mut options = options.clone();
+    options.fields.retain(|field| archive.fields.contains(field));
+    let mut videos = archive.videos;
+    for video in &mut videos {
+        project(video, &options);
+    }
+    write_videos(&options, &videos);
+}
+
+/// writes the videos to the output at once
+pub(crate) fn write_videos(options: &Options, videos: &[PartialVideoInfo]) {
+    let sink = create_sink(options, videos);
+    events::emit(options.log_format, Event::Info { message: "writeing....." });
+    sink.finish(videos);
+}
+
+fn create_sink<'a>(options: &'a Options, base: &[PartialVideoInfo]) -> Box<dyn Sink + 'a> {
+    if let Some(dir) = &options.out_dir {
+        return Box::new(period_dir::PeriodDirSink::new(dir, options))
+    }
+    match options.out_format {
+        OutFormat::Bin => Box::new(BinSink::new(options.out.as_deref(), options)),
+        OutFormat::Sqlite => Box::new(sqlite::SqliteSink::new(options.out.as_deref().unwrap(), options, base)),
+        OutFormat::Jsonl => Box::new(export::JsonlSink::new(options.out.as_deref(), options)),
+        OutFormat::Csv => Box::new(export::CsvSink::new(options.out.as_deref(), options)),
+        OutFormat::Parquet => Box::new(parquet::ParquetSink::new(options.out.as_deref(), options)),
+    }
+}
+
+/// the videos in a bin and the fields got
+pub(crate) struct Archive {
+    pub(crate) fields: Vec<FieldName>,
+    pub(crate) videos: Vec<PartialVideoInfo>,
+}
+
+/// reads the bin. the bin compressed with zstd is decompressed.
+/// the error is prefixed with the path.
+pub(crate) fn read_archive(path: &str) -> Result<Archive, String> {
+    let file = File::open(path).map_err(|err| format!("{}: {}", path, err))?;
+    let bin = read_bin(file).map_err(|err| format!("{}: {}", path, err))?;
+    let fields = match bin.fields() {
+        None => FieldName::all_values().to_vec(),
+        Some(fields) => fields.iter()
+            .map(|field| field.parse().map_err(|err| format!("{}: {}", path, err)))
+            .collect::<Result<_, _>>()?,
+    };
+    Ok(Archive {
+        fields,
+        videos: bin.into_partial_videos(),
+    })
+}
+
+/// reads --base. it must be got with the same fields as --fields
+/// since the videos not got again are written as they are.
+pub(crate) fn read_base(options: &Options) -> Option<Vec<PartialVideoInfo>> {
+    let path = options.base.as_ref()?;
+    let archive = read_archive(path)
+        .unwrap_or_else(|err| events::exit_with_error(options.log_format, &format!("base: {}", err)));
+    if !same_fields(&archive.fields, &options.fields) {
+        events::exit_with_error(options.log_format, &format!("base: {}: got with {} but --fields is {}",
+                                                             path, field_list(&archive.fields), field_list(&options.fields)));
+    }
+    Some(archive.videos)
+}
+
+/// whether the fields are same regardless of the order
+pub(crate) fn same_fields(a: &[FieldName], b: &[FieldName]) -> bool {
+    a.iter().all(|field| b.contains(field)) && b.iter().all(|field| a.contains(field))
+}
+
+/// like "contentId,title"
+pub(crate) fn field_list(fields: &[FieldName]) -> String {
+    fields.iter().map(|field| field.to_str()).collect::<Vec<_>>().join(",")
+}
+
+/// the fields not in --fields are None.
+/// the fields got as null are the default values so that the videos got with all fields can be written as NewVideoInfo.
+fn to_partial_video_info(video: VideoInfo, last_modified: DateTime<FixedOffset>, options: &Options) -> PartialVideoInfo {
+    fn or_default<T: Default>(options: &Options, field: FieldName, value: Option<T>) -> Option<T> {
+        value.or_else(|| if options.has_field(field) { Some(T::default()) } else { None })
+    }
+    let mut video = PartialVideoInfo {
+        last_modified: last_modified.with_timezone(&Utc),
+        content_id: video.content_id.unwrap(),
+        title: or_default(options, FieldName::Title, video.title),
+        description: video.description,
+        view_counter: or_default(options, FieldName::ViewCounter, video.view_counter),
+        mylist_counter: or_default(options, FieldName::MylistCounter, video.mylist_counter),
+        length_seconds: or_default(options, FieldName::LengthSeconds, video.length_seconds),
+        thumbnail_url: video.thumbnail_url,
+        start_time: video.start_time.unwrap().with_timezone(&Utc),
+        last_res_body: video.last_res_body,
+        comment_counter: or_default(options, FieldName::CommentCounter, video.comment_counter),
+        last_comment_time: video.last_comment_time.map(|x| x.with_timezone(&Utc)),
+        category_tags: video.category_tags,
+        tags: or_default(options, FieldName::Tags, video.tags),
+        genre: video.genre,
+    };
+    // --top gets viewCounter and lengthSeconds even if not in --fields
+    project(&mut video, options);
+    video
+}
+
+/// clears the fields not in --fields
+fn project(video: &mut PartialVideoInfo, options: &Options) {
+    fn clear<T>(options: &Options, field: FieldName, value: &mut Option<T>) {
+        if !options.has_field(field) {
+            *value = None;
+        }
+    }
+    clear(options, FieldName::Title, &mut video.title);
+    clear(options, FieldName::Description, &mut video.description);
+    clear(options, FieldName::ViewCounter, &mut video.view_counter);
+    clear(options, FieldName::MylistCounter, &mut video.mylist_counter);
+    clear(options, FieldName::LengthSeconds, &mut video.length_seconds);
+    clear(options, FieldName::ThumbnailUrl, &mut video.thumbnail_url);
+    clear(options, FieldName::LastResBody, &mut video.last_res_body);
+    clear(options, FieldName::CommentCounter, &mut video.comment_counter);
+    clear(options, FieldName::LastCommentTime, &mut video.last_comment_time);
+    clear(options, FieldName::CategoryTags, &mut video.category_tags);
+    clear(options, FieldName::Tags, &mut video.tags);
+    clear(options, FieldName::Genre, &mut video.genre);
+}
+
+/// the output file or stdout.
+/// file is written to temporary file and renamed on finish
+/// so that interrupted or failed run never leaves broken file.
+pub(crate) struct OutFile {
+    name: Option<String>,
+    temp_name: Option<String>,
+    pub(crate) writer: BufWriter<Either<Stdout, File>>,
+}
+
+impl OutFile {
+    pub(crate) fn create(name: Option<&str>) -> OutFile {
+        let temp_name = name.map(|name| format!("{}.part", name));
+        let out = match &temp_name {
+            None => Left(stdout()),
+            Some(name) => {
+                create_dir_all(Path::new(&name).parent().unwrap()).unwrap();
+                Right(File::create(name).unwrap())
+            }
+        };
+        OutFile {
+            name: name.map(|x| x.to_owned()),
+            temp_name,
+            writer: BufWriter::new(out),
+        }
+    }
+
+    /// opens the file to append to. the file is written directly since it cannot be renamed
+    pub(crate) fn append(name: Option<&str>) -> OutFile {
+        let out = match name {
+            None => Left(stdout()),
+            Some(name) => {
+                create_dir_all(Path::new(&name).parent().unwrap()).unwrap();
+                Right(OpenOptions::new().create(true).append(true).open(name).unwrap())
+            }
+        };
+        OutFile {
+            name: name.map(|x| x.to_owned()),
+            temp_name: None,
+            writer: BufWriter::new(out),
+        }
+    }
+
+    /// creates or appends to the file as --watchlist requires
+    pub(crate) fn open(name: Option<&str>, options: &Options) -> OutFile {
+        if options.appends_output() {
+            OutFile::append(name)
+        } else {
+            OutFile::create(name)
+        }
+    }
+
+    /// true if nothing is written to the file before. always true for stdout
+    pub(crate) fn is_new(&self) -> bool {
+        match self.writer.get_ref() {
+            Left(_) => true,
+            Right(file) => file.metadata().unwrap().len() == 0,
+        }
+    }
+
+    pub(crate) fn finish(mut self) {
+        self.writer.flush().unwrap();
+        drop(self.writer);
+
+        if let (Some(temp_name), Some(name)) = (&self.temp_name, &self.name) {
+            rename(temp_name, name).unwrap();
+        }
+    }
+}
+
+/// writes bincode of Vec<NewVideoInfo>, or PartialBin if --fields is narrowed, at last. compressed with zstd if --compress.
+/// the videos are written as PartialBin with the names of the fields if --fields is specified.
+struct BinSink<'a> {
+    out: OutFile,
+    options: &'a Options,
+}
+
+impl <'a> BinSink<'a> {
+    fn new(name: Option<&str>, options: &'a Options) -> BinSink<'a> {
+        BinSink {
+            out: OutFile::create(name),
+            options,
+        }
+    }
+}
+
+fn write_bin<W: Write>(writer: W, videos: &[PartialVideoInfo], options: &Options) {
+    let written = if options.is_projected() {
+        let fields = options.fields.iter().map(|field| field.to_str().to_owned()).collect::<Vec<_>>();
+        write_partial(writer, &fields, videos)
+    } else {
+        write_full(writer, videos)
+    };
+    written.unwrap_or_else(|err| events::exit_with_error(options.log_format, &format!("out: {}", err)));
+}
+
+impl Sink for BinSink<'_> {
+    fn write_period(&mut self, _period: &Period, _videos: &[&PartialVideoInfo]) {
+    }
+
+    fn finish(mut self: Box<Self>, videos: &[PartialVideoInfo]) {
+        if self.options.compress {
+            let mut encoder = zstd::Encoder::new(&mut self.out.writer, 0).unwrap();
+            write_bin(&mut encoder, videos, self.options);
+            encoder.finish().unwrap();
+        } else {
+            write_bin(&mut self.out.writer, videos, self.options);
+        }
+        self.out.finish();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use crate::options::options_of;
+    use tempfile::tempdir;
+
+    fn date(value: &str) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339(value).unwrap()
+    }
+
+    const SINCE: &str = "2020-01-01T00:00:00+09:00";
+    const UNTIL: &str = "2020-01-08T00:00:00+09:00";
+    const VERSION: &str = "2020-12-01T05:00:00+09:00";
+
+    fn base_video(content_id: &str, views: u32) -> PartialVideoInfo {
+        PartialVideoInfo {
+            last_modified: date("2020-11-01T05:00:00+09:00").with_timezone(&Utc),
+            content_id: content_id.to_owned(),
+            title: Some(content_id.to_owned()),
+            description: Some(format!("description of {}", content_id)),
+            view_counter: Some(views),
+            mylist_counter: Some(0),
+            length_seconds: Some(std::time::Duration::from_secs(60)),
+            thumbnail_url: Some(format!("https://example.com/{}", content_id)),
+            start_time: date(SINCE).with_timezone(&Utc),
+            last_res_body: Some("res".to_owned()),
+            comment_counter: Some(0),
+            last_comment_time: None,
+            category_tags: None,
+            tags: Some(Vec::new()),
+            genre: None,
+        }
+    }
+
+    /// the video got from server. description, thumbnailUrl and lastResBody are not got if refreshed
+    fn got_video(content_id: &str, views: u32, refresh: bool) -> VideoInfo {
+        let mut video = serde_json::json!({
+            "contentId": content_id,
+            "title": content_id,
+            "viewCounter": views,
+            "startTime": SINCE,
+        });
+        if !refresh {
+            video["description"] = serde_json::json!(format!("new description of {}", content_id));
+        }
+        serde_json::from_value(video).unwrap()
+    }
+
+    fn packet(videos: Vec<VideoInfo>, refresh: bool) -> Packet {
+        Packet {
+            since: date(SINCE),
+            until: date(UNTIL),
+            last_modified: date(VERSION),
+            total_count: videos.len(),
+            videos,
+            versions: None,
+            refresh,
+        }
+    }
+
+    /// runs with the packets and reads the output
+    fn run_with(base: Vec<PartialVideoInfo>, packets: Vec<Packet>) -> Vec<PartialVideoInfo> {
+        let dir = tempdir().unwrap();
+        let out = dir.path().join("out.bin").to_str().unwrap().to_owned();
+        let options = options_of(&["get-nico-data", "--out", &out]);
+        let (sender, receiver) = mpsc::channel();
+        for packet in packets {
+            sender.send(packet).unwrap();
+        }
+        drop(sender);
+        run(receiver, &options, base);
+        read_archive(&out).unwrap().videos
+    }
+
+    fn find<'a>(videos: &'a [PartialVideoInfo], content_id: &str) -> &'a PartialVideoInfo {
+        videos.iter().find(|video| video.content_id == content_id).unwrap()
+    }
+
+    #[test]
+    fn base_kept() {
+        let videos = run_with(vec![base_video("sm1", 10), base_video("sm2", 20)],
+                              vec![packet(vec![got_video("sm2", 25, true)], true)]);
+        assert_eq!(videos.len(), 2);
+        let kept = find(&videos, "sm1");
+        assert_eq!(kept.view_counter, Some(10));
+        assert_eq!(kept.last_modified, date("2020-11-01T05:00:00+09:00").with_timezone(&Utc));
+    }
+
+    #[test]
+    fn refreshed_keeps_fields_not_got() {
+        let videos = run_with(vec![base_video("sm1", 10)],
+                              vec![packet(vec![got_video("sm1", 15, true)], true)]);
+        let refreshed = find(&videos, "sm1");
+        assert_eq!(refreshed.view_counter, Some(15));
+        assert_eq!(refreshed.last_modified, date(VERSION).with_timezone(&Utc));
+        assert_eq!(refreshed.description.as_deref(), Some("description of sm1"));
+        assert_eq!(refreshed.thumbnail_url.as_deref(), Some("https://example.com/sm1"));
+        assert_eq!(refreshed.last_res_body.as_deref(), Some("res"));
+    }
+
+    #[test]
+    fn not_refreshed_replaces_all_fields() {
+        let videos = run_with(vec![base_video("sm1", 10)],
+                              vec![packet(vec![got_video("sm1", 15, false)], false)]);
+        let replaced = find(&videos, "sm1");
+        assert_eq!(replaced.description.as_deref(), Some("new description of sm1"));
+        assert_eq!(replaced.thumbnail_url, None);
+    }
+
+    #[test]
+    fn new_video_appended() {
+        let videos = run_with(vec![base_video("sm1", 10)],
+                              vec![packet(vec![got_video("sm2", 5, false)], false)]);
+        assert_eq!(videos.iter().map(|video| video.content_id.as_str()).collect::<Vec<_>>(), vec!["sm1", "sm2"]);
+        assert_eq!(find(&videos, "sm2").view_counter, Some(5));
+    }
+
+    #[test]
+    fn duplicated_in_period() {
+        let videos = run_with(vec![base_video("sm1", 10)], vec![
+            packet(vec![got_video("sm2", 5, false), got_video("sm2", 6, false)], false),
+            packet(vec![got_video("sm1", 11, true), got_video("sm1", 12, true)], true),
+        ]);
+        assert_eq!(videos.iter().map(|video| video.content_id.as_str()).collect::<Vec<_>>(), vec!["sm1", "sm2"]);
+        // the later one is used
+        assert_eq!(find(&videos, "sm2").view_counter, Some(6));
+        let refreshed = find(&videos, "sm1");
+        assert_eq!(refreshed.view_counter, Some(12));
+        assert_eq!(refreshed.description.as_deref(), Some("description of sm1"));
+    }
+}
@@ -0,0 +1,127 @@
+use serde::Serialize;
+use chrono::{DateTime, Utc};
+use structs::PartialVideoInfo;
+use nico_snapshot_api::FieldName;
+use crate::options::Options;
+use super::{Sink, Period, OutFile};
+
+/// PartialVideoInfo for the formats read by other than rust.
+/// times are in RFC 3339 and length is in seconds.
+/// the fields not got because of --fields are None and skipped.
+#[derive(Serialize)]
+struct ExportedVideo<'a, Tags: Serialize> {
+    last_modified: &'a DateTime<Utc>,
+    content_id: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<&'a Option<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    view_counter: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mylist_counter: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    length_seconds: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thumbnail_url: Option<&'a Option<String>>,
+    start_time: &'a DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_res_body: Option<&'a Option<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    comment_counter: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_comment_time: Option<&'a Option<DateTime<Utc>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    category_tags: Option<&'a Option<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tags: Option<Tags>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    genre: Option<&'a Option<String>>,
+}
+
+impl <'a, Tags: Serialize> ExportedVideo<'a, Tags> {
+    fn new(video: &'a PartialVideoInfo, tags: impl FnOnce() -> Tags, options: &Options) -> Self {
+        let field = |field| options.has_field(field);
+        ExportedVideo {
+            last_modified: &video.last_modified,
+            content_id: &video.content_id,
+            title: video.title.as_deref().filter(|_| field(FieldName::Title)),
+            description: Some(&video.description).filter(|_| field(FieldName::Description)),
+            view_counter: video.view_counter.filter(|_| field(FieldName::ViewCounter)),
+            mylist_counter: video.mylist_counter.filter(|_| field(FieldName::MylistCounter)),
+            length_seconds: video.length_seconds.map(|length| length.as_secs()).filter(|_| field(FieldName::LengthSeconds)),
+            thumbnail_url: Some(&video.thumbnail_url).filter(|_| field(FieldName::ThumbnailUrl)),
+            start_time: &video.start_time,
+            last_res_body: Some(&video.last_res_body).filter(|_| field(FieldName::LastResBody)),
+            comment_counter: video.comment_counter.filter(|_| field(FieldName::CommentCounter)),
+            last_comment_time: Some(&video.last_comment_time).filter(|_| field(FieldName::LastCommentTime)),
+            category_tags: Some(&video.category_tags).filter(|_| field(FieldName::CategoryTags)),
+            tags: if field(FieldName::Tags) { Some(tags()) } else { None },
+            genre: Some(&video.genre).filter(|_| field(FieldName::Genre)),
+        }
+    }
+}
+
+/// writes a json object per video per line
+pub(crate) struct JsonlSink<'a> {
+    out: OutFile,
+    options: &'a Options,
+}
+
+impl <'a> JsonlSink<'a> {
+    pub(crate) fn new(name: Option<&str>, options: &'a Options) -> JsonlSink<'a> {
+        JsonlSink {
+            out: OutFile::open(name, options),
+            options,
+        }
+    }
+}
+
+impl Sink for JsonlSink<'_> {
+    fn write_period(&mut self, _period: &Period, _videos: &[&PartialVideoInfo]) {
+    }
+
+    fn finish(mut self: Box<Self>, videos: &[PartialVideoInfo]) {
+        use std::io::Write;
+        for video in videos {
+            serde_json::to_writer(&mut self.out.writer, &ExportedVideo::new(video, || video.tags.as_deref().unwrap_or_default(), self.options)).unwrap();
+            writeln!(self.out.writer).unwrap();
+        }
+        self.out.finish();
+    }
+}
+
+/// writes csv with header. tags are joined with the separator.
+/// the header is not written when appended to the existing file.
+pub(crate) struct CsvSink<'a> {
+    out: OutFile,
+    options: &'a Options,
+}
+
+impl <'a> CsvSink<'a> {
+    pub(crate) fn new(name: Option<&str>, options: &'a Options) -> CsvSink<'a> {
+        CsvSink {
+            out: OutFile::open(name, options),
+            options,
+        }
+    }
+}
+
+impl Sink for CsvSink<'_> {
+    fn write_period(&mut self, _period: &Period, _videos: &[&PartialVideoInfo]) {
+    }
+
+    fn finish(mut self: Box<Self>, videos: &[PartialVideoInfo]) {
+        let options = self.options;
+        let has_headers = self.out.is_new();
+        let mut writer = csv::WriterBuilder::new()
+            .has_headers(has_headers)
+            .from_writer(&mut self.out.writer);
+        for video in videos {
+            writer.serialize(ExportedVideo::new(video, || video.tags.as_deref().unwrap_or_default().join(&options.tag_separator), options)).unwrap();
+        }
+        writer.flush().unwrap();
+        drop(writer);
+        self.out.finish();
+    }
+}
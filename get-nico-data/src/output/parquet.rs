@@ -0,0 +1,116 @@
+use std::sync::Arc;
+use arrow_array::{ArrayRef, RecordBatch, StringArray, TimestampMicrosecondArray, UInt32Array, UInt64Array};
+use arrow_array::builder::{ListBuilder, StringBuilder};
+use arrow_schema::{DataType, Field, Schema, TimeUnit};
+use chrono::{DateTime, Utc};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use structs::PartialVideoInfo;
+use nico_snapshot_api::FieldName;
+use crate::options::Options;
+use super::{Sink, Period, OutFile};
+
+/// count of videos in a row group
+const ROW_GROUP_SIZE: usize = 64 * 1024;
+
+/// writes parquet file.
+/// times are UTC timestamp in microseconds, length is in seconds and tags are list of string.
+/// the columns not got because of --fields are not written.
+pub(crate) struct ParquetSink<'a> {
+    out: OutFile,
+    options: &'a Options,
+}
+
+impl <'a> ParquetSink<'a> {
+    pub(crate) fn new(name: Option<&str>, options: &'a Options) -> ParquetSink<'a> {
+        ParquetSink {
+            out: OutFile::create(name),
+            options,
+        }
+    }
+}
+
+impl Sink for ParquetSink<'_> {
+    fn write_period(&mut self, _period: &Period, _videos: &[&PartialVideoInfo]) {
+    }
+
+    fn finish(mut self: Box<Self>, videos: &[PartialVideoInfo]) {
+        let (fields, _) = select(columns(&[]), self.options);
+        let schema = Arc::new(Schema::new(fields));
+        let props = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .build();
+        let mut writer = ArrowWriter::try_new(&mut self.out.writer, schema.clone(), Some(props)).unwrap();
+        for chunk in videos.chunks(ROW_GROUP_SIZE) {
+            let (_, arrays) = select(columns(chunk), self.options);
+            writer.write(&RecordBatch::try_new(schema.clone(), arrays).unwrap()).unwrap();
+        }
+        writer.close().unwrap();
+        self.out.finish();
+    }
+}
+
+/// the columns got by --fields
+fn select(columns: Vec<Column>, options: &Options) -> (Vec<Field>, Vec<ArrayRef>) {
+    columns.into_iter()
+        .filter(|(name, _, _)| name.is_none_or(|name| options.has_field(name)))
+        .map(|(_, field, array)| (field, array))
+        .unzip()
+}
+
+/// field name in the api, None if always got, schema field, and the values.
+type Column = (Option<FieldName>, Field, ArrayRef);
+
+fn columns(videos: &[PartialVideoInfo]) -> Vec<Column> {
+    fn timestamps(iter: impl Iterator<Item = Option<DateTime<Utc>>>) -> ArrayRef {
+        Arc::new(iter.map(|time| time.map(|time| time.timestamp_micros()))
+            .collect::<TimestampMicrosecondArray>()
+            .with_timezone("UTC"))
+    }
+    fn strings<'a>(iter: impl Iterator<Item = Option<&'a str>>) -> ArrayRef {
+        Arc::new(iter.collect::<StringArray>())
+    }
+
+    let timestamp = DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into()));
+    let mut tags = ListBuilder::new(StringBuilder::new());
+    for video in videos {
+        for tag in video.tags.iter().flatten() {
+            tags.values().append_value(tag);
+        }
+        tags.append(true);
+    }
+
+    vec![
+        (None, Field::new("last_modified", timestamp.clone(), false),
+         timestamps(videos.iter().map(|v| Some(v.last_modified)))),
+        (None, Field::new("content_id", DataType::Utf8, false),
+         strings(videos.iter().map(|v| Some(v.content_id.as_str())))),
+        (Some(FieldName::Title), Field::new("title", DataType::Utf8, false),
+         strings(videos.iter().map(|v| v.title.as_deref()))),
+        (Some(FieldName::Description), Field::new("description", DataType::Utf8, true),
+         strings(videos.iter().map(|v| v.description.as_deref()))),
+        (Some(FieldName::ViewCounter), Field::new("view_counter", DataType::UInt32, false),
+         Arc::new(videos.iter().map(|v| v.view_counter).collect::<UInt32Array>())),
+        (Some(FieldName::MylistCounter), Field::new("mylist_counter", DataType::UInt32, false),
+         Arc::new(videos.iter().map(|v| v.mylist_counter).collect::<UInt32Array>())),
+        (Some(FieldName::LengthSeconds), Field::new("length_seconds", DataType::UInt64, false),
+         Arc::new(videos.iter().map(|v| v.length_seconds.map(|length| length.as_secs())).collect::<UInt64Array>())),
+        (Some(FieldName::ThumbnailUrl), Field::new("thumbnail_url", DataType::Utf8, true),
+         strings(videos.iter().map(|v| v.thumbnail_url.as_deref()))),
+        (None, Field::new("start_time", timestamp.clone(), false),
+         timestamps(videos.iter().map(|v| Some(v.start_time)))),
+        (Some(FieldName::LastResBody), Field::new("last_res_body", DataType::Utf8, true),
+         strings(videos.iter().map(|v| v.last_res_body.as_deref()))),
+        (Some(FieldName::CommentCounter), Field::new("comment_counter", DataType::UInt32, false),
+         Arc::new(videos.iter().map(|v| v.comment_counter).collect::<UInt32Array>())),
+        (Some(FieldName::LastCommentTime), Field::new("last_comment_time", timestamp, true),
+         timestamps(videos.iter().map(|v| v.last_comment_time))),
+        (Some(FieldName::CategoryTags), Field::new("category_tags", DataType::Utf8, true),
+         strings(videos.iter().map(|v| v.category_tags.as_deref()))),
+        (Some(FieldName::Tags), Field::new("tags", DataType::List(Arc::new(Field::new("item", DataType::Utf8, true))), false),
+         Arc::new(tags.finish())),
+        (Some(FieldName::Genre), Field::new("genre", DataType::Utf8, true),
+         strings(videos.iter().map(|v| v.genre.as_deref()))),
+    ]
+}
@@ -0,0 +1,120 @@
+use std::fs::{create_dir_all, remove_file, rename, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use chrono::{DateTime, FixedOffset};
+use serde::{Serialize, Deserialize};
+use structs::PartialVideoInfo;
+use crate::options::{Options, OutFormat, SNAPSHOT_NAME_FORMAT};
+use crate::events;
+use super::{Sink, Period, create_sink};
+
+/// the name of the index in --out-dir if --index is not specified
+const INDEX_NAME: &str = "index.json";
+
+/// writes a file for each period to --out-dir and the index of those.
+/// the index is updated for each period so files of interrupted crawl can be used.
+/// the period got again replaces the entry with same range.
+pub(crate) struct PeriodDirSink<'a> {
+    dir: PathBuf,
+    index_path: PathBuf,
+    index: Index,
+    options: &'a Options,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct Index {
+    periods: Vec<IndexEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct IndexEntry {
+    since: DateTime<FixedOffset>,
+    until: DateTime<FixedOffset>,
+    last_modified: DateTime<FixedOffset>,
+    // relative to --out-dir
+    file: String,
+    count: usize,
+}
+
+impl <'a> PeriodDirSink<'a> {
+    pub(crate) fn new(dir: &str, options: &'a Options) -> PeriodDirSink<'a> {
+        let dir = PathBuf::from(dir);
+        create_dir_all(&dir).unwrap();
+        let index_path = options.index.as_ref().map_or_else(|| dir.join(INDEX_NAME), PathBuf::from);
+        if let Some(parent) = index_path.parent() {
+            create_dir_all(parent).unwrap();
+        }
+        PeriodDirSink {
+            index: read_index(&index_path, options),
+            index_path,
+            dir,
+            options,
+        }
+    }
+
+    fn write_index(&self) {
+        let mut temp = self.index_path.clone().into_os_string();
+        temp.push(".part");
+        serde_json::to_writer_pretty(File::create(&temp).unwrap(), &self.index).unwrap();
+        rename(temp, &self.index_path).unwrap();
+    }
+}
+
+/// reads the index. empty if not exists. exits if it's broken
+fn read_index(path: &Path, options: &Options) -> Index {
+    match File::open(path) {
+        Ok(file) => serde_json::from_reader(BufReader::new(file))
+            .unwrap_or_else(|err| events::exit_with_error(options.log_format,
+                                                          &format!("index: {}: {}", path.display(), err))),
+        Err(_) => Index::default(),
+    }
+}
+
+fn file_name(period: &Period, options: &Options) -> String {
+    let tz = options.since.timezone();
+    let extension = match options.out_format {
+        OutFormat::Bin => "bin",
+        OutFormat::Sqlite => "sqlite",
+        OutFormat::Jsonl => "jsonl",
+        OutFormat::Csv => "csv",
+        OutFormat::Parquet => "parquet",
+    };
+    format!("{}_{}_{}.{}",
+            period.since.with_timezone(&tz).format(SNAPSHOT_NAME_FORMAT),
+            period.until.with_timezone(&tz).format(SNAPSHOT_NAME_FORMAT),
+            period.last_modified.with_timezone(&tz).format(SNAPSHOT_NAME_FORMAT),
+            extension)
+}
+
+impl Sink for PeriodDirSink<'_> {
+    fn write_period(&mut self, period: &Period, videos: &[&PartialVideoInfo]) {
+        let file = file_name(period, self.options);
+        let mut options = self.options.clone();
+        options.out = Some(self.dir.join(&file).to_str().unwrap().to_owned());
+        options.out_dir = None;
+        let videos = videos.iter().map(|&video| video.clone()).collect::<Vec<_>>();
+        create_sink(&options, &[]).finish(&videos);
+
+        let old = self.index.periods.iter()
+            .position(|entry| entry.since == period.since && entry.until == period.until)
+            .map(|index| self.index.periods.remove(index));
+        self.index.periods.push(IndexEntry {
+            since: period.since,
+            until: period.until,
+            last_modified: period.last_modified,
+            file: file.clone(),
+            count: videos.len(),
+        });
+        self.index.periods.sort_by_key(|entry| entry.since);
+        self.write_index();
+
+        if let Some(old) = old {
+            if old.file != file {
+                let _ = remove_file(self.dir.join(old.file));
+            }
+        }
+    }
+
+    fn finish(self: Box<Self>, _videos: &[PartialVideoInfo]) {
+    }
+}
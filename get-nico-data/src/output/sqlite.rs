@@ -0,0 +1,195 @@
+use rusqlite::{params, Connection, Transaction};
+use rusqlite::types::Value;
+use structs::PartialVideoInfo;
+use chrono::Utc;
+use nico_snapshot_api::FieldName;
+use crate::options::Options;
+use crate::interrupt::is_interrupted;
+use super::{Sink, Period};
+use std::fs::create_dir_all;
+use std::path::Path;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS videos (
+    content_id TEXT PRIMARY KEY NOT NULL,
+    last_modified TEXT NOT NULL,
+    title TEXT,
+    description TEXT,
+    view_counter INTEGER,
+    mylist_counter INTEGER,
+    length_seconds INTEGER,
+    thumbnail_url TEXT,
+    start_time TEXT NOT NULL,
+    last_res_body TEXT,
+    comment_counter INTEGER,
+    last_comment_time TEXT,
+    category_tags TEXT,
+    genre TEXT
+);
+CREATE TABLE IF NOT EXISTS tags (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE
+);
+CREATE TABLE IF NOT EXISTS video_tags (
+    content_id TEXT NOT NULL REFERENCES videos (content_id),
+    tag_id INTEGER NOT NULL REFERENCES tags (id),
+    PRIMARY KEY (content_id, tag_id)
+);
+CREATE INDEX IF NOT EXISTS video_tags_tag_id ON video_tags (tag_id);
+CREATE TABLE IF NOT EXISTS crawls (
+    id INTEGER PRIMARY KEY,
+    since TEXT NOT NULL,
+    until TEXT,
+    started_at TEXT NOT NULL,
+    finished_at TEXT,
+    interrupted INTEGER
+);
+CREATE TABLE IF NOT EXISTS periods (
+    crawl_id INTEGER NOT NULL REFERENCES crawls (id),
+    since TEXT NOT NULL,
+    until TEXT NOT NULL,
+    last_modified TEXT NOT NULL,
+    count INTEGER NOT NULL,
+    committed_at TEXT NOT NULL
+);
+";
+
+/// the columns of videos and the fields of those. None if always got
+const COLUMNS: &[(Option<FieldName>, &str)] = &[
+    (None, "content_id"),
+    (None, "last_modified"),
+    (Some(FieldName::Title), "title"),
+    (Some(FieldName::Description), "description"),
+    (Some(FieldName::ViewCounter), "view_counter"),
+    (Some(FieldName::MylistCounter), "mylist_counter"),
+    (Some(FieldName::LengthSeconds), "length_seconds"),
+    (Some(FieldName::ThumbnailUrl), "thumbnail_url"),
+    (None, "start_time"),
+    (Some(FieldName::LastResBody), "last_res_body"),
+    (Some(FieldName::CommentCounter), "comment_counter"),
+    (Some(FieldName::LastCommentTime), "last_comment_time"),
+    (Some(FieldName::CategoryTags), "category_tags"),
+    (Some(FieldName::Genre), "genre"),
+];
+
+/// writes videos to sqlite database. a transaction is committed for each period
+/// so the database can be used even if crawling was interrupted.
+/// existing database is updated. the columns not got because of --fields are kept as they were.
+pub(crate) struct SqliteSink<'a> {
+    conn: Connection,
+    crawl_id: i64,
+    options: &'a Options,
+}
+
+impl <'a> SqliteSink<'a> {
+    pub(crate) fn new(path: &str, options: &'a Options, base: &[PartialVideoInfo]) -> SqliteSink<'a> {
+        create_dir_all(Path::new(path).parent().unwrap()).unwrap();
+        let mut conn = Connection::open(path).unwrap();
+        conn.execute_batch(SCHEMA).unwrap();
+
+        conn.execute(
+            "INSERT INTO crawls (since, until, started_at) VALUES (?1, ?2, ?3)",
+            params![
+                options.since.to_rfc3339(),
+                options.until.map(|x| x.to_rfc3339()),
+                Utc::now().to_rfc3339(),
+            ],
+        ).unwrap();
+        let crawl_id = conn.last_insert_rowid();
+
+        if !base.is_empty() {
+            let tx = conn.transaction().unwrap();
+            for video in base {
+                insert_video(&tx, video, options);
+            }
+            tx.commit().unwrap();
+        }
+
+        SqliteSink {
+            conn,
+            crawl_id,
+            options,
+        }
+    }
+}
+
+impl Sink for SqliteSink<'_> {
+    fn write_period(&mut self, period: &Period, videos: &[&PartialVideoInfo]) {
+        let tx = self.conn.transaction().unwrap();
+        for video in videos {
+            insert_video(&tx, video, self.options);
+        }
+        tx.execute(
+            "INSERT INTO periods (crawl_id, since, until, last_modified, count, committed_at) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                self.crawl_id,
+                period.since.to_rfc3339(),
+                period.until.to_rfc3339(),
+                period.last_modified.to_rfc3339(),
+                videos.len() as i64,
+                Utc::now().to_rfc3339(),
+            ],
+        ).unwrap();
+        tx.commit().unwrap();
+    }
+
+    fn finish(self: Box<Self>, _videos: &[PartialVideoInfo]) {
+        self.conn.execute(
+            "UPDATE crawls SET finished_at = ?1, interrupted = ?2 WHERE id = ?3",
+            params![
+                Utc::now().to_rfc3339(),
+                is_interrupted(),
+                self.crawl_id,
+            ],
+        ).unwrap();
+    }
+}
+
+/// the values of COLUMNS
+fn values(video: &PartialVideoInfo) -> Vec<Value> {
+    vec![
+        video.content_id.clone().into(),
+        video.last_modified.to_rfc3339().into(),
+        video.title.clone().into(),
+        video.description.clone().into(),
+        video.view_counter.into(),
+        video.mylist_counter.into(),
+        video.length_seconds.map(|length| length.as_secs() as i64).into(),
+        video.thumbnail_url.clone().into(),
+        video.start_time.to_rfc3339().into(),
+        video.last_res_body.clone().into(),
+        video.comment_counter.into(),
+        video.last_comment_time.map(|x| x.to_rfc3339()).into(),
+        video.category_tags.clone().into(),
+        video.genre.clone().into(),
+    ]
+}
+
+fn insert_video(tx: &Transaction, video: &PartialVideoInfo, options: &Options) {
+    let (names, values): (Vec<_>, Vec<_>) = COLUMNS.iter()
+        .zip(values(video))
+        .filter(|((field, _), _)| field.is_none_or(|field| options.has_field(field)))
+        .map(|((_, name), value)| (*name, value))
+        .unzip();
+    tx.prepare_cached(&format!(
+        "INSERT INTO videos ({}) VALUES ({}) ON CONFLICT (content_id) DO UPDATE SET {}",
+        names.join(", "),
+        (1..=names.len()).map(|index| format!("?{}", index)).collect::<Vec<_>>().join(", "),
+        names.iter().map(|name| format!("{0} = excluded.{0}", name)).collect::<Vec<_>>().join(", "),
+    )).unwrap().execute(values).unwrap();
+
+    if !options.has_field(FieldName::Tags) {
+        return
+    }
+    tx.prepare_cached("DELETE FROM video_tags WHERE content_id = ?1")
+        .unwrap().execute(params![video.content_id]).unwrap();
+    for tag in video.tags.iter().flatten() {
+        tx.prepare_cached("INSERT OR IGNORE INTO tags (name) VALUES (?1)")
+            .unwrap().execute(params![tag]).unwrap();
+        tx.prepare_cached(
+            "INSERT OR IGNORE INTO video_tags (content_id, tag_id) \
+             SELECT ?1, id FROM tags WHERE name = ?2",
+        ).unwrap().execute(params![video.content_id, tag]).unwrap();
+    }
+}
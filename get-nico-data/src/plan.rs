@@ -0,0 +1,470 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs::{create_dir_all, File};
+use std::path::Path;
+use chrono::{DateTime, Duration, FixedOffset, Utc};
+use nico_snapshot_api::FieldName;
+use serde::{Deserialize, Serialize};
+use structs::PartialVideoInfo;
+use crate::events::{self, Event};
+use crate::options::Options;
+use crate::output::{field_list, read_archive, same_fields, write_videos, Archive};
+
+/// exit status used when the shards cannot be merged
+pub(crate) const INCONSISTENT_EXIT_CODE: i32 = 2;
+
+/// the periods and the query of a crawl split into shards.
+/// each shard is a range of continuous periods and can be got by independent processes.
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct Plan {
+    // to check the shards are got for this plan
+    pub(crate) id: u64,
+    pub(crate) duration_seconds: i64,
+    pub(crate) query: String,
+    pub(crate) targets: Vec<String>,
+    pub(crate) periods: Vec<PlannedPeriod>,
+    pub(crate) shards: Vec<PlannedShard>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct PlannedPeriod {
+    pub(crate) since: DateTime<FixedOffset>,
+    pub(crate) until: DateTime<FixedOffset>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct PlannedShard {
+    pub(crate) since: DateTime<FixedOffset>,
+    pub(crate) until: DateTime<FixedOffset>,
+    // the count of periods in the shard
+    pub(crate) periods: usize,
+}
+
+/// the shard got by this process
+#[derive(Copy, Clone)]
+pub(crate) struct Shard {
+    pub(crate) plan_id: u64,
+    pub(crate) index: usize,
+}
+
+/// written next to the output of a shard to be checked when merged
+#[derive(Serialize, Deserialize)]
+struct ShardManifest {
+    plan_id: u64,
+    shard: usize,
+    // false if interrupted or given up
+    finished: bool,
+}
+
+impl Plan {
+    /// the periods are same as the ones a crawl with the options gets.
+    /// until is fixed to now if not specified so that all shards get same periods.
+    pub(crate) fn new(options: &Options, shards: usize) -> Plan {
+        let until = options.until.unwrap_or_else(|| Utc::now().with_timezone(&options.since.timezone()));
+        let mut periods = Vec::new();
+        let mut since = options.since;
+        while until - since >= Duration::minutes(1) {
+            let period_until = std::cmp::min(until, since + options.duration);
+            periods.push(PlannedPeriod { since, until: period_until });
+            since = period_until;
+        }
+
+        let shards = std::cmp::max(1, std::cmp::min(shards, periods.len()));
+        let mut planned = Vec::with_capacity(shards);
+        let mut start = 0;
+        for index in 0..shards {
+            let end = periods.len() * (index + 1) / shards;
+            if start < end {
+                planned.push(PlannedShard {
+                    since: periods[start].since,
+                    until: periods[end - 1].until,
+                    periods: end - start,
+                });
+            }
+            start = end;
+        }
+
+        Plan {
+            id: rand::random(),
+            duration_seconds: options.duration.num_seconds(),
+            query: options.query.clone(),
+            targets: options.targets.clone(),
+            periods,
+            shards: planned,
+        }
+    }
+}
+
+impl Plan {
+    /// the periods the shard gets
+    pub(crate) fn periods_of(&self, shard: usize) -> Vec<(DateTime<FixedOffset>, DateTime<FixedOffset>)> {
+        let start = self.shards[..shard].iter().map(|shard| shard.periods).sum::<usize>();
+        self.periods[start..start + self.shards[shard].periods].iter()
+            .map(|period| (period.since, period.until))
+            .collect()
+    }
+}
+
+pub(crate) fn read_plan(path: &str) -> Result<Plan, String> {
+    let file = File::open(path).map_err(|err| err.to_string())?;
+    serde_json::from_reader(file).map_err(|err| err.to_string())
+}
+
+pub(crate) fn write_plan(plan: &Plan, path: &str) {
+    create_dir_all(Path::new(path).parent().unwrap()).unwrap();
+    serde_json::to_writer_pretty(File::create(path).unwrap(), plan).unwrap();
+}
+
+/// the file the manifest of the shard is written to. None if written to stdout
+pub(crate) fn manifest_path(options: &Options) -> Option<String> {
+    options.out.as_ref().map(|out| format!("{}.shard.json", out))
+}
+
+pub(crate) fn write_manifest(shard: Shard, finished: bool, path: &str) {
+    let manifest = ShardManifest { plan_id: shard.plan_id, shard: shard.index, finished };
+    create_dir_all(Path::new(path).parent().unwrap()).unwrap();
+    serde_json::to_writer_pretty(File::create(path).unwrap(), &manifest).unwrap();
+}
+
+/// merges the outputs of the shards into --out.
+/// returns false without writing if the shards are not of the plan, not finished, not readable,
+/// some shards are missing, or the videos were got under different snapshot versions.
+/// written with the fields of the shards.
+pub(crate) fn merge(options: &Options, plan: &Plan, paths: &[String]) -> bool {
+    let shards = match read_shards(plan, paths, options.keep_mixed_versions) {
+        Ok(shards) => shards,
+        Err(problems) => {
+            for problem in &problems {
+                events::emit(options.log_format, Event::Error { message: &format!("merge: {}", problem) });
+            }
+            return false
+        }
+    };
+
+    let mut options = options.clone();
+    options.fields = shards.values().next().unwrap().fields.clone();
+    let list = merge_videos(shards);
+    events::emit(options.log_format, Event::Info {
+        message: &format!("merge: {} videos of {} shards", list.len(), plan.shards.len()),
+    });
+    write_videos(&options, &list);
+    true
+}
+
+/// reads the outputs of the shards by the index of shard.
+/// returns the problems if those cannot be merged.
+/// the shards got under different snapshot versions are merged only if `keep_mixed_versions`.
+/// all shards must be got with the same fields.
+fn read_shards(plan: &Plan, paths: &[String], keep_mixed_versions: bool)
+    -> Result<BTreeMap<usize, Archive>, Vec<String>> {
+    let mut problems = Vec::new();
+    let mut shards = BTreeMap::<usize, Archive>::new();
+    // the path and the fields of the first shard read
+    let mut first: Option<(&str, Vec<FieldName>)> = None;
+    // the shards got under each version
+    let mut versions = BTreeMap::<DateTime<Utc>, BTreeSet<usize>>::new();
+    for path in paths {
+        let manifest_path = format!("{}.shard.json", path);
+        let manifest = match File::open(&manifest_path).map_err(|err| err.to_string())
+            .and_then(|file| serde_json::from_reader::<_, ShardManifest>(file).map_err(|err| err.to_string())) {
+            Ok(manifest) => manifest,
+            Err(err) => {
+                problems.push(format!("{}: {}", manifest_path, err));
+                continue
+            }
+        };
+        if manifest.plan_id != plan.id {
+            problems.push(format!("{}: got for another plan", path));
+            continue
+        }
+        if !manifest.finished {
+            problems.push(format!("{}: shard #{} was not finished", path, manifest.shard));
+        }
+        if shards.contains_key(&manifest.shard) {
+            problems.push(format!("{}: shard #{} is specified twice", path, manifest.shard));
+            continue
+        }
+        let archive = match read_archive(path) {
+            Ok(archive) => archive,
+            Err(err) => {
+                problems.push(err);
+                continue
+            }
+        };
+        match &first {
+            None => first = Some((path, archive.fields.clone())),
+            Some((first_path, fields)) => if !same_fields(fields, &archive.fields) {
+                problems.push(format!("{}: got with {} but {} with {}",
+                                      path, field_list(&archive.fields), first_path, field_list(fields)));
+            }
+        }
+        for video in &archive.videos {
+            versions.entry(video.last_modified).or_default().insert(manifest.shard);
+        }
+        shards.insert(manifest.shard, archive);
+    }
+    for index in 0..plan.shards.len() {
+        if !shards.contains_key(&index) {
+            problems.push(format!("shard #{} is missing", index));
+        }
+    }
+    if versions.len() > 1 && !keep_mixed_versions {
+        let (newest, _) = versions.iter().next_back().unwrap();
+        // a shard is stale if any video in it is not got under the newest version
+        let stale = versions.iter()
+            .filter(|&(version, _)| version != newest)
+            .flat_map(|(_, shards)| shards.iter().copied())
+            .collect::<BTreeSet<_>>();
+        for (version, shards) in &versions {
+            problems.push(format!("got under the snapshot of {}: shards {}", version, shard_list(shards.iter())));
+        }
+        problems.push(format!("re-run shards {} with --plan and --shard to get them under the snapshot of {}, \
+                               or merge with --keep-mixed-versions to keep the version of each video",
+                              shard_list(stale.iter()), newest));
+    }
+
+    if problems.is_empty() {
+        Ok(shards)
+    } else {
+        Err(problems)
+    }
+}
+
+/// like "#0, #2"
+fn shard_list<'a>(shards: impl Iterator<Item = &'a usize>) -> String {
+    shards.map(|shard| format!("#{}", shard)).collect::<Vec<_>>().join(", ")
+}
+
+/// the videos of all shards. written once if duplicated as a crawl does
+fn merge_videos(shards: BTreeMap<usize, Archive>) -> Vec<PartialVideoInfo> {
+    let mut index_of = HashMap::new();
+    let mut list = Vec::<PartialVideoInfo>::new();
+    for video in shards.into_values().flat_map(|archive| archive.videos) {
+        match index_of.get(&video.content_id) {
+            Some(&index) => list[index] = video,
+            None => {
+                index_of.insert(video.content_id.clone(), list.len());
+                list.push(video);
+            }
+        }
+    }
+    list
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use structs::NewVideoInfo;
+    use structs::bin::write_partial;
+    use std::fs::OpenOptions;
+    use tempfile::tempdir;
+
+    fn date(value: &str) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339(value).unwrap()
+    }
+
+    /// two shards of two and one periods
+    fn plan() -> Plan {
+        let periods = [
+            ("2020-01-01T00:00:00+09:00", "2020-01-08T00:00:00+09:00"),
+            ("2020-01-08T00:00:00+09:00", "2020-01-15T00:00:00+09:00"),
+            ("2020-01-15T00:00:00+09:00", "2020-01-20T00:00:00+09:00"),
+        ];
+        Plan {
+            id: 12345,
+            duration_seconds: Duration::weeks(1).num_seconds(),
+            query: String::new(),
+            targets: Vec::new(),
+            periods: periods.iter()
+                .map(|&(since, until)| PlannedPeriod { since: date(since), until: date(until) })
+                .collect(),
+            shards: vec![
+                PlannedShard { since: date(periods[0].0), until: date(periods[1].1), periods: 2 },
+                PlannedShard { since: date(periods[2].0), until: date(periods[2].1), periods: 1 },
+            ],
+        }
+    }
+
+    fn video(content_id: &str, last_modified: &str) -> NewVideoInfo {
+        NewVideoInfo {
+            last_modified: date(last_modified).with_timezone(&Utc),
+            content_id: content_id.to_owned(),
+            title: content_id.to_owned(),
+            description: None,
+            view_counter: 0,
+            mylist_counter: 0,
+            length_seconds: std::time::Duration::from_secs(0),
+            thumbnail_url: None,
+            start_time: date("2020-01-02T00:00:00+09:00").with_timezone(&Utc),
+            last_res_body: None,
+            comment_counter: 0,
+            last_comment_time: None,
+            category_tags: None,
+            tags: Vec::new(),
+            genre: None,
+        }
+    }
+
+    /// writes the output of a shard and its manifest
+    fn write_shard(dir: &Path, name: &str, plan_id: u64, index: usize, finished: bool,
+                   videos: &[NewVideoInfo]) -> String {
+        let path = dir.join(name).to_str().unwrap().to_owned();
+        bincode::serialize_into(File::create(&path).unwrap(), videos).unwrap();
+        write_manifest(Shard { plan_id, index }, finished, &manifest_path_of(&path));
+        path
+    }
+
+    fn manifest_path_of(path: &str) -> String {
+        format!("{}.shard.json", path)
+    }
+
+    const VERSION: &str = "2020-12-01T05:00:00+09:00";
+
+    #[test]
+    fn periods_of_shards() {
+        let plan = plan();
+        assert_eq!(plan.periods_of(0), vec![
+            (date("2020-01-01T00:00:00+09:00"), date("2020-01-08T00:00:00+09:00")),
+            (date("2020-01-08T00:00:00+09:00"), date("2020-01-15T00:00:00+09:00")),
+        ]);
+        assert_eq!(plan.periods_of(1), vec![
+            (date("2020-01-15T00:00:00+09:00"), date("2020-01-20T00:00:00+09:00")),
+        ]);
+    }
+
+    #[test]
+    fn merged() {
+        let plan = plan();
+        let dir = tempdir().unwrap();
+        let paths = vec![
+            write_shard(dir.path(), "0.bin", plan.id, 0, true, &[video("sm1", VERSION), video("sm2", VERSION)]),
+            write_shard(dir.path(), "1.bin", plan.id, 1, true, &[video("sm2", VERSION), video("sm3", VERSION)]),
+        ];
+        let shards = read_shards(&plan, &paths, false).unwrap_or_else(|problems| panic!("{:?}", problems));
+        let videos = merge_videos(shards);
+        assert_eq!(videos.iter().map(|video| video.content_id.as_str()).collect::<Vec<_>>(),
+                   vec!["sm1", "sm2", "sm3"]);
+    }
+
+    #[test]
+    fn duplicated_shard() {
+        let plan = plan();
+        let dir = tempdir().unwrap();
+        let paths = vec![
+            write_shard(dir.path(), "0.bin", plan.id, 0, true, &[video("sm1", VERSION)]),
+            write_shard(dir.path(), "0-again.bin", plan.id, 0, true, &[video("sm1", VERSION)]),
+            write_shard(dir.path(), "1.bin", plan.id, 1, true, &[video("sm2", VERSION)]),
+        ];
+        let problems = read_shards(&plan, &paths, false).err().unwrap();
+        assert_eq!(problems.len(), 1, "{:?}", problems);
+        assert!(problems[0].contains("shard #0 is specified twice"), "{:?}", problems);
+    }
+
+    #[test]
+    fn missing_shard() {
+        let plan = plan();
+        let dir = tempdir().unwrap();
+        let paths = vec![
+            write_shard(dir.path(), "0.bin", plan.id, 0, true, &[video("sm1", VERSION)]),
+        ];
+        let problems = read_shards(&plan, &paths, false).err().unwrap();
+        assert_eq!(problems, vec!["shard #1 is missing".to_owned()]);
+    }
+
+    #[test]
+    fn missing_manifest() {
+        let plan = plan();
+        let dir = tempdir().unwrap();
+        let path = write_shard(dir.path(), "0.bin", plan.id, 0, true, &[video("sm1", VERSION)]);
+        std::fs::remove_file(manifest_path_of(&path)).unwrap();
+        let paths = vec![
+            path,
+            write_shard(dir.path(), "1.bin", plan.id, 1, true, &[video("sm2", VERSION)]),
+        ];
+        let problems = read_shards(&plan, &paths, false).err().unwrap();
+        assert_eq!(problems.len(), 2, "{:?}", problems);
+        assert!(problems[0].contains("0.bin.shard.json"), "{:?}", problems);
+        assert_eq!(problems[1], "shard #0 is missing");
+    }
+
+    #[test]
+    fn truncated_shard() {
+        let plan = plan();
+        let dir = tempdir().unwrap();
+        let path = write_shard(dir.path(), "0.bin", plan.id, 0, true, &[video("sm1", VERSION), video("sm2", VERSION)]);
+        let length = std::fs::metadata(&path).unwrap().len();
+        OpenOptions::new().write(true).open(&path).unwrap().set_len(length / 2).unwrap();
+        let paths = vec![
+            path,
+            write_shard(dir.path(), "1.bin", plan.id, 1, true, &[video("sm3", VERSION)]),
+        ];
+        let problems = read_shards(&plan, &paths, false).err().unwrap();
+        assert_eq!(problems.len(), 2, "{:?}", problems);
+        assert!(problems[0].contains("0.bin: "), "{:?}", problems);
+        assert_eq!(problems[1], "shard #0 is missing");
+    }
+
+    #[test]
+    fn unfinished_shard() {
+        let plan = plan();
+        let dir = tempdir().unwrap();
+        let paths = vec![
+            write_shard(dir.path(), "0.bin", plan.id, 0, true, &[video("sm1", VERSION)]),
+            write_shard(dir.path(), "1.bin", plan.id, 1, false, &[video("sm2", VERSION)]),
+        ];
+        let problems = read_shards(&plan, &paths, false).err().unwrap();
+        assert_eq!(problems.len(), 1, "{:?}", problems);
+        assert!(problems[0].contains("shard #1 was not finished"), "{:?}", problems);
+    }
+
+    #[test]
+    fn shard_of_another_plan() {
+        let plan = plan();
+        let dir = tempdir().unwrap();
+        let paths = vec![
+            write_shard(dir.path(), "0.bin", plan.id, 0, true, &[video("sm1", VERSION)]),
+            write_shard(dir.path(), "1.bin", plan.id + 1, 1, true, &[video("sm2", VERSION)]),
+        ];
+        let problems = read_shards(&plan, &paths, false).err().unwrap();
+        assert_eq!(problems.len(), 2, "{:?}", problems);
+        assert!(problems[0].contains("got for another plan"), "{:?}", problems);
+        assert_eq!(problems[1], "shard #1 is missing");
+    }
+
+    #[test]
+    fn mismatched_snapshot_versions() {
+        let plan = plan();
+        let dir = tempdir().unwrap();
+        let paths = vec![
+            write_shard(dir.path(), "0.bin", plan.id, 0, true, &[video("sm1", VERSION)]),
+            write_shard(dir.path(), "1.bin", plan.id, 1, true, &[video("sm2", "2020-12-02T05:00:00+09:00")]),
+        ];
+        let problems = read_shards(&plan, &paths, false).err().unwrap();
+        assert_eq!(problems.len(), 3, "{:?}", problems);
+        assert!(problems[0].ends_with("shards #0"), "{:?}", problems);
+        assert!(problems[1].ends_with("shards #1"), "{:?}", problems);
+        assert!(problems[2].starts_with("re-run shards #0 with"), "{:?}", problems);
+
+        let videos = merge_videos(read_shards(&plan, &paths, true).unwrap_or_else(|problems| panic!("{:?}", problems)));
+        assert_eq!(videos.iter().map(|video| video.last_modified).collect::<Vec<_>>(), vec![
+            date(VERSION).with_timezone(&Utc),
+            date("2020-12-02T05:00:00+09:00").with_timezone(&Utc),
+        ]);
+    }
+
+    #[test]
+    fn other_fields() {
+        let plan = plan();
+        let dir = tempdir().unwrap();
+        let partial = dir.path().join("1.bin").to_str().unwrap().to_owned();
+        let fields = vec!["contentId".to_owned(), "startTime".to_owned(), "viewCounter".to_owned()];
+        write_partial(File::create(&partial).unwrap(), &fields, &[video("sm2", VERSION).into()]).unwrap();
+        write_manifest(Shard { plan_id: plan.id, index: 1 }, true, &manifest_path_of(&partial));
+        let paths = vec![
+            write_shard(dir.path(), "0.bin", plan.id, 0, true, &[video("sm1", VERSION)]),
+            partial,
+        ];
+        let problems = read_shards(&plan, &paths, false).err().unwrap();
+        assert_eq!(problems.len(), 1, "{:?}", problems);
+        assert!(problems[0].contains("1.bin: got with contentId,startTime,viewCounter but"), "{:?}", problems);
+    }
+}
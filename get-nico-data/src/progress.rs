@@ -1,27 +1,39 @@
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle, ProgressDrawTarget};
 use std::fmt::Display;
+use structs::events::{LogEvent, LogFormat};
+use crate::events::{self, Event};
 
 pub struct ProgressStatus {
     progress: ProgressBar,
     prefix: String,
+    format: LogFormat,
 }
 
 impl ProgressStatus {
-    pub fn new(multi: &MultiProgress) -> ProgressStatus {
+    pub fn new(multi: &MultiProgress, format: LogFormat) -> ProgressStatus {
         let progress = multi.add(ProgressBar::new(1));
         set_style(&progress);
-        progress.enable_steady_tick(10);
+        if format == LogFormat::Text {
+            progress.enable_steady_tick(10);
+        }
         ProgressStatus {
             progress,
             prefix: String::new(),
+            format,
         }
     }
 
-    pub fn add_err(&mut self, p0: &str) {
-        self.progress.println(format!("err: {}", p0));
+    pub fn log(&self, event: Event) {
+        match self.format {
+            LogFormat::Text => if let Some(text) = event.text() {
+                self.progress.println(text)
+            },
+            LogFormat::Json => events::emit(self.format, event),
+        }
     }
+
     pub fn add_info(&mut self, p0: &str) {
-        self.progress.println(format!("inf: {}", p0));
+        self.log(Event::Info { message: p0 });
     }
 
     pub fn inc(&self) {
@@ -52,6 +64,14 @@ impl ProgressStatus {
     }
 }
 
+/// MultiProgress to be used for the format. progress bars are not drawn in json log format.
+pub fn new_multi_progress(format: LogFormat) -> MultiProgress {
+    match format {
+        LogFormat::Text => MultiProgress::new(),
+        LogFormat::Json => MultiProgress::with_draw_target(ProgressDrawTarget::hidden()),
+    }
+}
+
 fn set_style(progress: &ProgressBar) {
     progress.set_style(ProgressStyle::default_bar().template("[{elapsed_precise}] {bar:40blue} {pos:>7}/{len:7} {msg}")
         .progress_chars("##-"));
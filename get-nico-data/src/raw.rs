@@ -0,0 +1,255 @@
+use std::fs::{create_dir_all, read_dir, remove_dir_all, File};
+use std::io::{BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use chrono::{DateTime, FixedOffset};
+use serde::{Deserialize, Serialize};
+use nico_snapshot_api::{ResponseJson, VideoInfo};
+use crate::Packet;
+use crate::get_data_from_server::replace_refetched;
+
+/// the manifest of the period written after all pages are stored
+const PERIOD_FILE: &str = "period.json";
+
+/// the format of since and until in the name of the directory of a period.
+/// with seconds since periods may start in the same minute
+const PERIOD_NAME_FORMAT: &str = "%Y%m%dT%H%M%S";
+
+/// stores the raw json pages compressed with zstd
+/// as `<dir>/<since>_<until>/<offset>.json.zst` so that the archive can be rebuilt from those.
+/// the videos not in the base got again with all fields are stored as `refetch-<offset>.json.zst`.
+pub(crate) struct RawStore {
+    dir: PathBuf,
+    tz: FixedOffset,
+}
+
+/// written to the directory of the period when the period is finished.
+/// the pages not listed are ignored.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct RawPeriod {
+    pub(crate) since: DateTime<FixedOffset>,
+    pub(crate) until: DateTime<FixedOffset>,
+    pub(crate) last_modified: DateTime<FixedOffset>,
+    pub(crate) total_count: usize,
+    pub(crate) refresh: bool,
+    pub(crate) pages: Vec<RawPage>,
+    // the videos got again to replace those in pages. offset is of the videos not in the base
+    #[serde(default)]
+    pub(crate) refetch: Vec<RawPage>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct RawPage {
+    pub(crate) offset: u32,
+    // the version the page is got under
+    pub(crate) last_modified: DateTime<FixedOffset>,
+}
+
+impl RawStore {
+    pub(crate) fn new(dir: &str, tz: FixedOffset) -> RawStore {
+        RawStore {
+            dir: PathBuf::from(dir),
+            tz,
+        }
+    }
+
+    fn period_dir(&self, since: DateTime<FixedOffset>, until: DateTime<FixedOffset>) -> PathBuf {
+        self.dir.join(format!("{}_{}",
+                              since.with_timezone(&self.tz).format(PERIOD_NAME_FORMAT),
+                              until.with_timezone(&self.tz).format(PERIOD_NAME_FORMAT)))
+    }
+
+    /// removes the pages stored by previous run or attempt of the period
+    pub(crate) fn start_period(&self, since: DateTime<FixedOffset>, until: DateTime<FixedOffset>) {
+        let dir = self.period_dir(since, until);
+        if dir.exists() {
+            remove_dir_all(&dir).unwrap();
+        }
+        create_dir_all(&dir).unwrap();
+    }
+
+    pub(crate) fn write_page(&self, since: DateTime<FixedOffset>, until: DateTime<FixedOffset>, offset: u32, body: &str) {
+        self.write(&self.period_dir(since, until).join(page_file(offset)), body);
+    }
+
+    pub(crate) fn write_refetch(&self, since: DateTime<FixedOffset>, until: DateTime<FixedOffset>, offset: u32, body: &str) {
+        self.write(&self.period_dir(since, until).join(refetch_file(offset)), body);
+    }
+
+    fn write(&self, path: &Path, body: &str) {
+        let mut encoder = zstd::Encoder::new(File::create(path).unwrap(), 0).unwrap();
+        encoder.write_all(body.as_bytes()).unwrap();
+        encoder.finish().unwrap();
+    }
+
+    pub(crate) fn finish_period(&self, period: &RawPeriod) {
+        let path = self.period_dir(period.since, period.until).join(PERIOD_FILE);
+        serde_json::to_writer_pretty(File::create(path).unwrap(), period).unwrap();
+    }
+}
+
+/// reads the finished periods in the directory and sends those in order of since.
+/// the periods not finished are skipped.
+/// returns the error with the path if the directory or a file in it cannot be read.
+pub(crate) fn rebuild(dir: &str, sender: &Sender<Packet>) -> Result<(), String> {
+    let mut periods = Vec::new();
+    for entry in read_dir(dir).map_err(|err| format!("{}: {}", dir, err))? {
+        let path = entry.map_err(|err| format!("{}: {}", dir, err))?.path();
+        let period_path = path.join(PERIOD_FILE);
+        if !period_path.exists() {
+            continue
+        }
+        let period: RawPeriod = File::open(&period_path).map_err(|err| err.to_string())
+            .and_then(|file| serde_json::from_reader(BufReader::new(file)).map_err(|err| err.to_string()))
+            .map_err(|err| format!("{}: {}", period_path.display(), err))?;
+        periods.push((path, period));
+    }
+    periods.sort_by_key(|(_, period)| period.since);
+
+    for (path, period) in periods {
+        let mut videos = Vec::<VideoInfo>::new();
+        let mut versions = Vec::new();
+        for page in &period.pages {
+            let json = read_page(&path.join(page_file(page.offset)))?;
+            versions.extend(std::iter::repeat_n(page.last_modified, json.data.len()));
+            videos.extend(json.data);
+        }
+        for page in &period.refetch {
+            replace_refetched(&mut videos, read_page(&path.join(refetch_file(page.offset)))?.data);
+        }
+        let mixed = versions.iter().any(|&version| version != period.last_modified);
+        sender.send(Packet {
+            since: period.since,
+            until: period.until,
+            last_modified: period.last_modified,
+            videos,
+            versions: if mixed { Some(versions) } else { None },
+            total_count: period.total_count,
+            refresh: period.refresh,
+        }).unwrap();
+    }
+    Ok(())
+}
+
+fn page_file(offset: u32) -> String {
+    format!("{:06}.json.zst", offset)
+}
+
+fn refetch_file(offset: u32) -> String {
+    format!("refetch-{:06}.json.zst", offset)
+}
+
+fn read_page(path: &Path) -> Result<ResponseJson, String> {
+    File::open(path)
+        .and_then(zstd::Decoder::new)
+        .map_err(|err| err.to_string())
+        .and_then(|decoder| serde_json::from_reader(BufReader::new(decoder)).map_err(|err| err.to_string()))
+        .map_err(|err| format!("{}: {}", path.display(), err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use tempfile::tempdir;
+
+    fn date(value: &str) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339(value).unwrap()
+    }
+
+    fn body(videos: serde_json::Value) -> String {
+        serde_json::json!({
+            "meta": { "status": 200, "id": "test", "totalCount": 2 },
+            "data": videos,
+        }).to_string()
+    }
+
+    #[test]
+    fn rebuild_refreshed_period() {
+        let dir = tempdir().unwrap();
+        let store = RawStore::new(dir.path().to_str().unwrap(), FixedOffset::east_opt(9 * 3600).unwrap());
+        let since = date("2020-01-01T00:00:00+09:00");
+        let until = date("2020-01-02T00:00:00+09:00");
+        let version = date("2020-12-01T05:00:00+09:00");
+        store.start_period(since, until);
+        // refreshed without description. sm2 is not in the base
+        store.write_page(since, until, 0, &body(serde_json::json!([
+            { "contentId": "sm1", "viewCounter": 10 },
+            { "contentId": "sm2", "viewCounter": 20 },
+        ])));
+        store.write_refetch(since, until, 0, &body(serde_json::json!([
+            { "contentId": "sm2", "viewCounter": 20, "description": "got again" },
+        ])));
+        store.finish_period(&RawPeriod {
+            since,
+            until,
+            last_modified: version,
+            total_count: 2,
+            refresh: true,
+            pages: vec![RawPage { offset: 0, last_modified: version }],
+            refetch: vec![RawPage { offset: 0, last_modified: version }],
+        });
+
+        let (sender, receiver) = mpsc::channel();
+        rebuild(dir.path().to_str().unwrap(), &sender).unwrap();
+        drop(sender);
+        let packets = receiver.iter().collect::<Vec<_>>();
+        assert_eq!(packets.len(), 1);
+        let packet = &packets[0];
+        assert!(packet.refresh);
+        assert_eq!((packet.since, packet.until, packet.last_modified), (since, until, version));
+        assert_eq!(packet.versions, None);
+        let videos = packet.videos.iter()
+            .map(|video| (video.content_id.as_deref().unwrap(), video.description.as_deref()))
+            .collect::<Vec<_>>();
+        assert_eq!(videos, vec![("sm1", None), ("sm2", Some("got again"))]);
+    }
+
+    #[test]
+    fn unfinished_period_skipped() {
+        let dir = tempdir().unwrap();
+        let store = RawStore::new(dir.path().to_str().unwrap(), FixedOffset::east_opt(9 * 3600).unwrap());
+        let since = date("2020-01-01T00:00:00+09:00");
+        let until = date("2020-01-02T00:00:00+09:00");
+        store.start_period(since, until);
+        store.write_page(since, until, 0, &body(serde_json::json!([{ "contentId": "sm1" }])));
+
+        let (sender, receiver) = mpsc::channel();
+        rebuild(dir.path().to_str().unwrap(), &sender).unwrap();
+        drop(sender);
+        assert_eq!(receiver.iter().count(), 0);
+    }
+
+    #[test]
+    fn periods_in_same_minute() {
+        let dir = tempdir().unwrap();
+        let store = RawStore::new(dir.path().to_str().unwrap(), FixedOffset::east_opt(9 * 3600).unwrap());
+        let first = date("2020-01-01T00:00:00+09:00");
+        let second = date("2020-01-01T00:00:30+09:00");
+        let third = date("2020-01-01T00:01:00+09:00");
+        store.start_period(first, second);
+        store.write_page(first, second, 0, &body(serde_json::json!([{ "contentId": "sm1" }])));
+        store.start_period(second, third);
+        assert_ne!(store.period_dir(first, second), store.period_dir(second, third));
+        assert!(store.period_dir(first, second).join(page_file(0)).exists());
+    }
+
+    #[test]
+    fn broken_period_reported() {
+        let dir = tempdir().unwrap();
+        let store = RawStore::new(dir.path().to_str().unwrap(), FixedOffset::east_opt(9 * 3600).unwrap());
+        let since = date("2020-01-01T00:00:00+09:00");
+        let until = date("2020-01-02T00:00:00+09:00");
+        store.start_period(since, until);
+        let period_path = store.period_dir(since, until).join(PERIOD_FILE);
+        std::fs::write(&period_path, "{").unwrap();
+
+        let (sender, _receiver) = mpsc::channel();
+        let err = rebuild(dir.path().to_str().unwrap(), &sender).err().unwrap();
+        assert!(err.starts_with(&period_path.display().to_string()), "{}", err);
+
+        std::fs::remove_file(&period_path).unwrap();
+        let err = rebuild(dir.path().join("missing").to_str().unwrap(), &sender).err().unwrap();
+        assert!(err.contains("missing: "), "{}", err);
+    }
+}
@@ -0,0 +1,111 @@
+use std::cmp::{max, min};
+use std::time::Duration;
+use rand::Rng;
+
+/// how http requests are retried
+#[derive(Copy, Clone)]
+pub struct RetryPolicy {
+    /// timeout of a request
+    pub timeout: Duration,
+    /// wait after the first failure. doubled for each failure
+    pub initial_wait: Duration,
+    /// the maximum wait without Retry-After
+    pub max_wait: Duration,
+    /// None to retry forever
+    pub max_attempts: Option<u32>,
+    /// time to give up since the first attempt of a request. None to retry forever
+    pub deadline: Option<Duration>,
+}
+
+impl RetryPolicy {
+    /// the wait before next attempt after `failures` failures.
+    /// the exponential backoff with jitter but not shorter than Retry-After.
+    pub(crate) fn wait(&self, failures: u32, retry_after: Option<Duration>) -> Duration {
+        let backoff = self.initial_wait
+            .checked_mul(1 << min(failures.saturating_sub(1), 16))
+            .map_or(self.max_wait, |backoff| min(backoff, self.max_wait));
+        let jitter = rand::thread_rng().gen_range(Duration::from_secs(0), backoff / 2 + Duration::from_millis(1));
+        max(backoff / 2 + jitter, retry_after.unwrap_or_default())
+    }
+
+    /// true if no more attempts are allowed after `attempts` attempts,
+    /// or the next attempt will be after the deadline.
+    pub(crate) fn is_exhausted(&self, attempts: u32, elapsed_at_next: Duration) -> bool {
+        self.max_attempts.is_some_and(|max_attempts| max_attempts <= attempts)
+            || self.deadline.is_some_and(|deadline| deadline < elapsed_at_next)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            timeout: Duration::from_secs(30),
+            initial_wait: Duration::from_secs(1),
+            max_wait: Duration::from_secs(60),
+            max_attempts: None,
+            deadline: None,
+        }
+    }
+
+    /// asserts the wait is the backoff with jitter: between the half and the whole of it
+    fn assert_jitter(policy: &RetryPolicy, failures: u32, backoff: Duration) {
+        for _ in 0..100 {
+            let wait = policy.wait(failures, None);
+            assert!(backoff / 2 <= wait && wait <= backoff + Duration::from_millis(1),
+                    "{} failures: {:?} is not in the jitter of {:?}", failures, wait, backoff);
+        }
+    }
+
+    #[test]
+    fn exponential_backoff_with_jitter() {
+        let policy = policy();
+        assert_jitter(&policy, 1, Duration::from_secs(1));
+        assert_jitter(&policy, 2, Duration::from_secs(2));
+        assert_jitter(&policy, 3, Duration::from_secs(4));
+        assert_jitter(&policy, 6, Duration::from_secs(32));
+    }
+
+    #[test]
+    fn backoff_limited_by_max_wait() {
+        let policy = policy();
+        assert_jitter(&policy, 7, Duration::from_secs(60));
+        assert_jitter(&policy, 100, Duration::from_secs(60));
+        assert_jitter(&policy, u32::MAX, Duration::from_secs(60));
+    }
+
+    #[test]
+    fn retry_after_is_floor() {
+        let policy = policy();
+        for _ in 0..100 {
+            assert_eq!(policy.wait(1, Some(Duration::from_secs(10))), Duration::from_secs(10));
+        }
+        // longer than max wait
+        assert_eq!(policy.wait(100, Some(Duration::from_secs(120))), Duration::from_secs(120));
+        // shorter Retry-After doesn't shorten the backoff
+        assert!(policy.wait(6, Some(Duration::from_secs(1))) >= Duration::from_secs(16));
+    }
+
+    #[test]
+    fn exhausted_by_attempts() {
+        let policy = RetryPolicy { max_attempts: Some(3), ..policy() };
+        assert!(!policy.is_exhausted(1, Duration::from_secs(0)));
+        assert!(!policy.is_exhausted(2, Duration::from_secs(0)));
+        assert!(policy.is_exhausted(3, Duration::from_secs(0)));
+    }
+
+    #[test]
+    fn exhausted_by_deadline() {
+        let policy = RetryPolicy { deadline: Some(Duration::from_secs(60)), ..policy() };
+        assert!(!policy.is_exhausted(100, Duration::from_secs(59)));
+        assert!(!policy.is_exhausted(100, Duration::from_secs(60)));
+        assert!(policy.is_exhausted(1, Duration::from_secs(61)));
+    }
+
+    #[test]
+    fn never_exhausted_without_limits() {
+        assert!(!policy().is_exhausted(u32::MAX, Duration::from_secs(u64::MAX / 2)));
+    }
+}
@@ -0,0 +1,138 @@
+use std::fs::{create_dir_all, File};
+use std::path::Path;
+use chrono::{DateTime, FixedOffset};
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use rand::seq::index;
+use serde::Serialize;
+use crate::options::Options;
+
+/// max offset of requests allowed by the server
+pub(crate) const MAX_OFFSET: u32 = 100_000;
+
+/// how many pages of each period are got in sampling crawl.
+/// since pages are chosen per period, the sample is stratified by start time.
+#[derive(Serialize, Copy, Clone)]
+pub(crate) struct Sample {
+    pub(crate) size: SampleSize,
+    pub(crate) seed: u64,
+}
+
+#[derive(Serialize, Copy, Clone)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum SampleSize {
+    // pages for each period
+    Pages(u32),
+    // fraction of pages for each period
+    Fraction(f64),
+}
+
+impl Sample {
+    /// the count of pages to be got in the period
+    pub(crate) fn sampled_pages(&self, total_count: u32, page_size: u32) -> u32 {
+        let pages = all_pages(total_count, page_size);
+        match self.size {
+            SampleSize::Pages(count) => std::cmp::min(count, pages),
+            SampleSize::Fraction(fraction) => (pages as f64 * fraction).ceil() as u32,
+        }
+    }
+
+    /// the rng to choose the pages of the period.
+    /// seeded from the seed and the period so that restarts and re-runs choose the same pages
+    pub(crate) fn rng_of(&self, since: DateTime<FixedOffset>, until: DateTime<FixedOffset>) -> StdRng {
+        let mut seed = <StdRng as SeedableRng>::Seed::default();
+        seed[..8].copy_from_slice(&self.seed.to_le_bytes());
+        seed[8..16].copy_from_slice(&since.timestamp().to_le_bytes());
+        seed[16..24].copy_from_slice(&until.timestamp().to_le_bytes());
+        StdRng::from_seed(seed)
+    }
+
+    /// chooses offsets of the pages to be got in increasing order
+    pub(crate) fn choose_offsets(&self, total_count: u32, page_size: u32, rng: &mut impl Rng) -> Vec<u32> {
+        let pages = all_pages(total_count, page_size);
+        let amount = self.sampled_pages(total_count, page_size);
+        let mut offsets = index::sample(rng, pages as usize, amount as usize)
+            .into_iter()
+            .map(|page| page as u32 * page_size)
+            .collect::<Vec<_>>();
+        offsets.sort();
+        offsets
+    }
+}
+
+/// the count of pages can be got in the period
+fn all_pages(total_count: u32, page_size: u32) -> u32 {
+    std::cmp::min(total_count, MAX_OFFSET + page_size).div_ceil(page_size)
+}
+
+/// the parameters of the sample written next to the output
+#[derive(Serialize)]
+struct SampleParams<'a> {
+    #[serde(flatten)]
+    sample: &'a Sample,
+    since: DateTime<FixedOffset>,
+    until: Option<DateTime<FixedOffset>>,
+    duration_seconds: i64,
+    query: &'a str,
+    targets: &'a [String],
+}
+
+/// the file the parameters are written to. None if written to stdout
+pub(crate) fn params_path(options: &Options) -> Option<String> {
+    match (&options.out, &options.out_dir) {
+        (Some(out), _) => Some(format!("{}.sample.json", out)),
+        (None, Some(dir)) => Some(Path::new(dir).join("sample.json").to_str().unwrap().to_owned()),
+        (None, None) => None,
+    }
+}
+
+pub(crate) fn write_params(sample: &Sample, path: &str, options: &Options) {
+    let params = SampleParams {
+        sample,
+        since: options.since,
+        until: options.until,
+        duration_seconds: options.duration.num_seconds(),
+        query: &options.query,
+        targets: &options.targets,
+    };
+    create_dir_all(Path::new(path).parent().unwrap()).unwrap();
+    serde_json::to_writer_pretty(File::create(path).unwrap(), &params).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(hour: u32) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339(&format!("2020-01-01T{:02}:00:00+09:00", hour)).unwrap()
+    }
+
+    fn offsets(seed: u64, since: u32) -> Vec<u32> {
+        let sample = Sample { size: SampleSize::Pages(5), seed };
+        sample.choose_offsets(100_000, 100, &mut sample.rng_of(time(since), time(since + 1)))
+    }
+
+    #[test]
+    fn same_pages_for_same_seed_and_period() {
+        assert_eq!(offsets(1, 0), offsets(1, 0));
+        // not depending on the periods chosen before
+        let _ = offsets(1, 1);
+        assert_eq!(offsets(1, 2), offsets(1, 2));
+    }
+
+    #[test]
+    fn other_pages_for_other_seed_or_period() {
+        assert_ne!(offsets(1, 0), offsets(2, 0));
+        assert_ne!(offsets(1, 0), offsets(1, 1));
+    }
+
+    #[test]
+    fn offsets_in_range() {
+        let sample = Sample { size: SampleSize::Fraction(0.5), seed: 1 };
+        let offsets = sample.choose_offsets(1050, 100, &mut sample.rng_of(time(0), time(1)));
+        // 11 pages
+        assert_eq!(offsets.len(), 6);
+        assert!(offsets.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(offsets.iter().all(|&offset| offset % 100 == 0 && offset <= 1000));
+    }
+}
@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+use chrono::{DateTime, FixedOffset};
+use serde::Serialize;
+use nico_snapshot_api::VideoInfo;
+
+/// the list of videos sorted on server
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub(crate) enum TopList {
+    ByViews,
+    ByLength,
+}
+
+/// candidates of the top videos by watch sum got from the heads of
+/// the lists sorted by view counter and by length in decreasing order.
+/// the watch sum of a video in neither of the heads is at most
+/// the smallest view counter times the smallest length got,
+/// so the top is guaranteed once enough candidates are above that cutoff.
+pub(crate) struct TopCandidates {
+    count: usize,
+    videos: HashMap<String, VideoInfo>,
+    // the smallest values got from each list. None until a page is got
+    min_views: Option<u64>,
+    min_length: Option<u64>,
+    // true if all videos of a list are got
+    exhausted: bool,
+}
+
+impl TopCandidates {
+    pub(crate) fn new(count: usize) -> TopCandidates {
+        TopCandidates {
+            count,
+            videos: HashMap::new(),
+            min_views: None,
+            min_length: None,
+            exhausted: false,
+        }
+    }
+
+    /// adds a page of the list. exhausted is true if no more videos are in the list
+    pub(crate) fn add(&mut self, list: TopList, page: Vec<VideoInfo>, exhausted: bool) {
+        if let Some(last) = page.last() {
+            match list {
+                TopList::ByViews => self.min_views = Some(views(last)),
+                TopList::ByLength => self.min_length = Some(length(last)),
+            }
+        }
+        for video in page {
+            self.videos.insert(video.content_id.clone().unwrap(), video);
+        }
+        self.exhausted |= exhausted;
+    }
+
+    /// the upper bound of watch sum of the videos not got. None if unknown
+    pub(crate) fn cutoff(&self) -> Option<u64> {
+        if self.exhausted {
+            return Some(0)
+        }
+        Some(self.min_views? * self.min_length?)
+    }
+
+    /// true if no video not got can be in the top
+    pub(crate) fn is_guaranteed(&self) -> bool {
+        if self.exhausted {
+            return true
+        }
+        let cutoff = match self.cutoff() {
+            Some(cutoff) => cutoff,
+            None => return false,
+        };
+        self.videos.values().filter(|video| watch_sum(video) >= cutoff).count() >= self.count
+    }
+
+    pub(crate) fn result(&self, last_modified: DateTime<FixedOffset>, offset_limited: bool) -> TopResult {
+        TopResult {
+            count: self.count,
+            candidates: self.videos.len(),
+            cutoff: self.cutoff(),
+            min_views: self.min_views,
+            min_length_seconds: self.min_length,
+            guaranteed: self.is_guaranteed(),
+            offset_limited,
+            last_modified,
+        }
+    }
+
+    /// the top videos in decreasing order of watch sum. ties are in order of content id
+    pub(crate) fn into_top(self) -> Vec<VideoInfo> {
+        let mut videos = self.videos.into_values().collect::<Vec<_>>();
+        videos.sort_by(|a, b| watch_sum(b).cmp(&watch_sum(a)).then_with(|| a.content_id.cmp(&b.content_id)));
+        videos.truncate(self.count);
+        videos
+    }
+}
+
+fn views(video: &VideoInfo) -> u64 {
+    video.view_counter.unwrap_or_default() as u64
+}
+
+fn length(video: &VideoInfo) -> u64 {
+    video.length_seconds.unwrap_or_default().as_secs()
+}
+
+/// same as the key of watch-sum ranking of sort-ranking
+fn watch_sum(video: &VideoInfo) -> u64 {
+    views(video) * length(video)
+}
+
+/// the result of top crawl
+#[derive(Serialize)]
+pub(crate) struct TopResult {
+    count: usize,
+    // videos got from the heads of both lists
+    candidates: usize,
+    cutoff: Option<u64>,
+    min_views: Option<u64>,
+    min_length_seconds: Option<u64>,
+    // true if the top is same as the top of all videos
+    guaranteed: bool,
+    // true if stopped by the max offset of the server before guaranteed
+    offset_limited: bool,
+    last_modified: DateTime<FixedOffset>,
+}
+
+impl TopResult {
+    pub(crate) fn is_guaranteed(&self) -> bool {
+        self.guaranteed
+    }
+
+    /// the line for human
+    pub(crate) fn summary(&self) -> String {
+        let cutoff = self.cutoff.map_or_else(|| "unknown".to_owned(), |cutoff| cutoff.to_string());
+        if self.guaranteed {
+            format!("top {} by watch sum is guaranteed. {} candidates, cutoff {}",
+                    self.count, self.candidates, cutoff)
+        } else {
+            format!("top {} by watch sum is provisional. {} candidates, cutoff {}{}",
+                    self.count, self.candidates, cutoff,
+                    if self.offset_limited { ". reached the max offset of the server" } else { "" })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn video(content_id: &str, views: u32, length: u64) -> VideoInfo {
+        serde_json::from_value(serde_json::json!({
+            "contentId": content_id,
+            "viewCounter": views,
+            "lengthSeconds": length,
+        })).unwrap()
+    }
+
+    fn ids(videos: &[VideoInfo]) -> Vec<&str> {
+        videos.iter().map(|video| video.content_id.as_deref().unwrap()).collect()
+    }
+
+    #[test]
+    fn unknown_until_both_lists_got() {
+        let mut top = TopCandidates::new(1);
+        top.add(TopList::ByViews, vec![video("sm1", 1000, 100), video("sm2", 500, 10)], false);
+        assert_eq!(top.cutoff(), None);
+        assert!(!top.is_guaranteed());
+    }
+
+    #[test]
+    fn guaranteed_when_enough_above_cutoff() {
+        let mut top = TopCandidates::new(2);
+        top.add(TopList::ByViews, vec![video("sm1", 1000, 100), video("sm2", 500, 10)], false);
+        top.add(TopList::ByLength, vec![video("sm3", 10, 600), video("sm1", 1000, 100)], false);
+        // 500 views * 100 seconds
+        assert_eq!(top.cutoff(), Some(50_000));
+        // only sm1 (100_000) is above the cutoff
+        assert!(!top.is_guaranteed());
+
+        top.add(TopList::ByViews, vec![video("sm4", 400, 200)], false);
+        // 400 views * 100 seconds. sm1 and sm4 (80_000) are above
+        assert_eq!(top.cutoff(), Some(40_000));
+        assert!(top.is_guaranteed());
+        assert_eq!(ids(&top.into_top()), vec!["sm1", "sm4"]);
+    }
+
+    #[test]
+    fn equal_to_cutoff_is_enough() {
+        let mut top = TopCandidates::new(1);
+        top.add(TopList::ByViews, vec![video("sm1", 100, 10)], false);
+        top.add(TopList::ByLength, vec![video("sm1", 100, 10)], false);
+        assert_eq!(top.cutoff(), Some(1000));
+        assert!(top.is_guaranteed());
+    }
+
+    #[test]
+    fn exhausted_list_is_guaranteed() {
+        let mut top = TopCandidates::new(3);
+        top.add(TopList::ByViews, vec![video("sm1", 100, 10), video("sm2", 50, 300)], true);
+        assert_eq!(top.cutoff(), Some(0));
+        assert!(top.is_guaranteed());
+        assert_eq!(ids(&top.into_top()), vec!["sm2", "sm1"]);
+    }
+
+    #[test]
+    fn empty_exhausted_list_is_guaranteed() {
+        let mut top = TopCandidates::new(1);
+        top.add(TopList::ByLength, Vec::new(), true);
+        assert!(top.is_guaranteed());
+        assert!(top.into_top().is_empty());
+    }
+
+    #[test]
+    fn count_larger_than_candidates() {
+        let mut top = TopCandidates::new(10);
+        top.add(TopList::ByViews, vec![video("sm1", 1000, 100), video("sm2", 500, 10)], false);
+        top.add(TopList::ByLength, vec![video("sm3", 10, 600)], false);
+        // every candidate is above the cutoff but the videos not got may be in the top 10
+        assert!(!top.is_guaranteed());
+        assert_eq!(ids(&top.into_top()), vec!["sm1", "sm3", "sm2"]);
+    }
+
+    #[test]
+    fn ties_in_order_of_content_id() {
+        let mut top = TopCandidates::new(2);
+        top.add(TopList::ByViews, vec![video("sm3", 100, 10), video("sm1", 100, 10), video("sm2", 100, 10)], true);
+        assert_eq!(ids(&top.into_top()), vec!["sm1", "sm2"]);
+    }
+
+    #[test]
+    fn duplicated_candidates_counted_once() {
+        let mut top = TopCandidates::new(2);
+        top.add(TopList::ByViews, vec![video("sm1", 1000, 100)], false);
+        top.add(TopList::ByLength, vec![video("sm1", 1000, 100)], false);
+        assert!(!top.is_guaranteed());
+    }
+}
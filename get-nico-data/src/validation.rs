@@ -0,0 +1,231 @@
+use std::collections::{HashMap, HashSet};
+use chrono::{DateTime, FixedOffset};
+use serde::Serialize;
+use crate::Packet;
+
+/// checks the got videos for duplicates and gaps.
+/// a video got twice in a period or got again in another period is a duplicate,
+/// and a period with fewer unique videos than total_count returned from server is a shortfall.
+/// pages may shift while paginating so both periods are to be refetched.
+pub(crate) struct Validation {
+    // the period the content id is got first
+    first_period: HashMap<String, PeriodRange>,
+    report: ValidationReport,
+}
+
+impl Validation {
+    pub(crate) fn new() -> Validation {
+        Validation {
+            first_period: HashMap::new(),
+            report: ValidationReport {
+                periods: 0,
+                received: 0,
+                unique: 0,
+                duplicates: Vec::new(),
+                shortfalls: Vec::new(),
+                refetch: Vec::new(),
+                mixed: Vec::new(),
+            },
+        }
+    }
+
+    pub(crate) fn check(&mut self, packet: &Packet) {
+        let period = PeriodRange { since: packet.since, until: packet.until };
+        let mut in_period = HashSet::new();
+        let mut duplicated_in_period = false;
+        for content_id in packet.videos.iter().map(|video| video.content_id.as_ref().unwrap()) {
+            if !in_period.insert(content_id) {
+                duplicated_in_period = true;
+                self.report.duplicates.push(Duplicate {
+                    content_id: content_id.clone(),
+                    period,
+                    first_period: period,
+                });
+                continue
+            }
+            match self.first_period.get(content_id) {
+                Some(&first_period) => self.report.duplicates.push(Duplicate {
+                    content_id: content_id.clone(),
+                    period,
+                    first_period,
+                }),
+                None => {
+                    self.first_period.insert(content_id.clone(), period);
+                    self.report.unique += 1;
+                }
+            }
+        }
+        self.report.periods += 1;
+        self.report.received += packet.videos.len();
+        if in_period.len() < packet.total_count {
+            self.report.shortfalls.push(Shortfall {
+                period,
+                total_count: packet.total_count,
+                unique: in_period.len(),
+            });
+        }
+        // duplicates in a period mean pages were shifted so some videos may be skipped
+        if in_period.len() != packet.total_count || duplicated_in_period {
+            self.report.refetch.push(period);
+        }
+        if let Some(versions) = &packet.versions {
+            let mut versions = versions.clone();
+            versions.sort();
+            versions.dedup();
+            self.report.mixed.push(MixedPeriod { period, versions });
+        }
+    }
+
+    pub(crate) fn report(self) -> ValidationReport {
+        self.report
+    }
+}
+
+/// duplicates, shortfalls and periods to be refetched
+#[derive(Serialize)]
+pub(crate) struct ValidationReport {
+    periods: usize,
+    // videos received including duplicates
+    received: usize,
+    unique: usize,
+    duplicates: Vec<Duplicate>,
+    shortfalls: Vec<Shortfall>,
+    refetch: Vec<PeriodRange>,
+    // periods got under different snapshot versions with --keep-mixed-versions
+    mixed: Vec<MixedPeriod>,
+}
+
+#[derive(Serialize, Copy, Clone)]
+struct PeriodRange {
+    since: DateTime<FixedOffset>,
+    until: DateTime<FixedOffset>,
+}
+
+#[derive(Serialize)]
+struct Duplicate {
+    content_id: String,
+    period: PeriodRange,
+    // same as period if duplicated in a period
+    first_period: PeriodRange,
+}
+
+#[derive(Serialize)]
+struct MixedPeriod {
+    period: PeriodRange,
+    versions: Vec<DateTime<FixedOffset>>,
+}
+
+#[derive(Serialize)]
+struct Shortfall {
+    period: PeriodRange,
+    total_count: usize,
+    unique: usize,
+}
+
+impl ValidationReport {
+    pub(crate) fn is_valid(&self) -> bool {
+        self.duplicates.is_empty() && self.refetch.is_empty()
+    }
+
+    /// the line for human
+    pub(crate) fn summary(&self) -> String {
+        format!("validation: {} videos in {} periods. {} duplicates, {} shortfalls, {} periods should be refetched, \
+                 {} periods with mixed versions",
+                self.unique, self.periods, self.duplicates.len(), self.shortfalls.len(), self.refetch.len(),
+                self.mixed.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nico_snapshot_api::VideoInfo;
+
+    fn time(hour: u32) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339(&format!("2020-01-01T{:02}:00:00+09:00", hour)).unwrap()
+    }
+
+    fn packet(hour: u32, content_ids: &[&str], total_count: usize) -> Packet {
+        Packet {
+            since: time(hour),
+            until: time(hour + 1),
+            last_modified: time(23),
+            videos: content_ids.iter()
+                .map(|content_id| serde_json::from_value::<VideoInfo>(serde_json::json!({
+                    "contentId": content_id,
+                })).unwrap())
+                .collect(),
+            versions: None,
+            total_count,
+            refresh: false,
+        }
+    }
+
+    fn refetched(report: &ValidationReport) -> Vec<DateTime<FixedOffset>> {
+        report.refetch.iter().map(|period| period.since).collect()
+    }
+
+    #[test]
+    fn valid() {
+        let mut validation = Validation::new();
+        validation.check(&packet(0, &["sm1", "sm2"], 2));
+        validation.check(&packet(1, &["sm3"], 1));
+        let report = validation.report();
+        assert!(report.is_valid());
+        assert_eq!((report.periods, report.received, report.unique), (2, 3, 3));
+        assert!(report.shortfalls.is_empty());
+    }
+
+    #[test]
+    fn duplicated_in_period() {
+        let mut validation = Validation::new();
+        validation.check(&packet(0, &["sm1", "sm2", "sm1"], 3));
+        let report = validation.report();
+        assert!(!report.is_valid());
+        assert_eq!((report.received, report.unique), (3, 2));
+        assert_eq!(report.duplicates.len(), 1);
+        assert_eq!(report.duplicates[0].content_id, "sm1");
+        assert_eq!(report.duplicates[0].first_period.since, time(0));
+        // the page shifted so a video may be skipped
+        assert_eq!(report.shortfalls.len(), 1);
+        assert_eq!(refetched(&report), vec![time(0)]);
+    }
+
+    #[test]
+    fn duplicated_across_periods() {
+        let mut validation = Validation::new();
+        validation.check(&packet(0, &["sm1", "sm2"], 2));
+        validation.check(&packet(1, &["sm2", "sm3"], 2));
+        let report = validation.report();
+        assert!(!report.is_valid());
+        assert_eq!(report.unique, 3);
+        assert_eq!(report.duplicates.len(), 1);
+        assert_eq!(report.duplicates[0].period.since, time(1));
+        assert_eq!(report.duplicates[0].first_period.since, time(0));
+        // each period has all of total_count
+        assert!(report.refetch.is_empty());
+    }
+
+    #[test]
+    fn shortfall() {
+        let mut validation = Validation::new();
+        validation.check(&packet(0, &["sm1", "sm2"], 2));
+        validation.check(&packet(1, &["sm3"], 3));
+        let report = validation.report();
+        assert!(!report.is_valid());
+        assert_eq!(report.shortfalls.len(), 1);
+        assert_eq!(report.shortfalls[0].period.since, time(1));
+        assert_eq!((report.shortfalls[0].total_count, report.shortfalls[0].unique), (3, 1));
+        assert_eq!(refetched(&report), vec![time(1)]);
+    }
+
+    #[test]
+    fn more_than_total_count() {
+        // videos added while paginating
+        let mut validation = Validation::new();
+        validation.check(&packet(0, &["sm1", "sm2", "sm3"], 2));
+        let report = validation.report();
+        assert!(report.shortfalls.is_empty());
+        assert_eq!(refetched(&report), vec![time(0)]);
+    }
+}
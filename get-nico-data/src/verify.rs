@@ -0,0 +1,369 @@
+use std::collections::HashMap;
+use std::sync::mpsc::{self, Receiver};
+use std::time::Duration;
+use chrono::{DateTime, FixedOffset, Utc};
+use rand::seq::SliceRandom;
+use reqwest::Client;
+use serde::Serialize;
+use tokio::runtime::Runtime;
+use nico_snapshot_api::FieldName;
+use structs::PartialVideoInfo;
+use crate::Packet;
+use crate::events::{self, Event};
+use crate::get_data_from_server::{get_periods, get_snapshot_version, Context};
+use crate::options::Options;
+use crate::output::{read_archive, OutFile};
+use crate::progress::new_multi_progress;
+use std::io::Write;
+
+/// exit status used when the archive doesn't match the server
+pub(crate) const MISMATCH_EXIT_CODE: i32 = 2;
+/// exit status used when the server serves other snapshot than the archive
+pub(crate) const OTHER_SNAPSHOT_EXIT_CODE: i32 = 1;
+
+/// the result of verify
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Verified {
+    Matched,
+    Mismatched,
+    /// the server serves other snapshot than the archive and nothing is compared
+    OtherSnapshot,
+}
+
+impl Verified {
+    pub(crate) fn exit_code(self) -> Option<i32> {
+        match self {
+            Verified::Matched => None,
+            Verified::Mismatched => Some(MISMATCH_EXIT_CODE),
+            Verified::OtherSnapshot => Some(OTHER_SNAPSHOT_EXIT_CODE),
+        }
+    }
+}
+
+/// fields got to verify. the fields not in the archive are not got
+const VERIFY_FIELDS: &[FieldName] = &[
+    FieldName::ContentId,
+    FieldName::Title,
+    FieldName::ViewCounter,
+    FieldName::MylistCounter,
+    FieldName::LengthSeconds,
+    FieldName::StartTime,
+    FieldName::CommentCounter,
+];
+
+/// gets randomly chosen periods of the archive again and compares with it.
+/// the report is written to --out.
+/// nothing is compared if the server serves other snapshot than the archive.
+pub(crate) fn verify(runtime: &mut Runtime, client: &Client, options: &Options, path: &str) -> Verified {
+    let archive = read_archive(path)
+        .unwrap_or_else(|err| events::exit_with_error(options.log_format, &format!("verify: {}", err)));
+    let fields = VERIFY_FIELDS.iter()
+        .copied()
+        .filter(|field| archive.fields.contains(field))
+        .collect::<Vec<_>>();
+    let archive = archive.videos;
+    let version = archive.iter().map(|video| video.last_modified).max();
+    let periods = choose_periods(&archive, options);
+    let progress = new_multi_progress(options.log_format);
+
+    let (mut report, server_version) = crossbeam::thread::scope(|s| {
+        let (sender, receiver) = mpsc::channel::<Packet>();
+        let checker = s.spawn(|_| check(receiver, &archive, version));
+        s.spawn(|_| {
+            std::thread::sleep(Duration::from_secs(1));
+            progress.join().unwrap();
+        });
+        let server_version = runtime.block_on(async {
+            let mut ctx = Context::new(client, &progress, options, sender);
+            let server_version = get_snapshot_version(&mut ctx).await?.last_modified.with_timezone(&Utc);
+            if Some(server_version) == version {
+                get_periods(&mut ctx, &periods, &fields).await;
+            }
+            Some(server_version)
+        });
+        (checker.join().unwrap(), server_version)
+    }).unwrap();
+
+    report.archive_version = version;
+    report.server_version = server_version;
+    let verified = report.verified();
+    if verified == Verified::OtherSnapshot {
+        events::emit(options.log_format, Event::Error {
+            message: &format!("verify: the archive is snapshot {} but the server serves snapshot {}. \
+                               only the archive of the latest snapshot can be verified",
+                              version.unwrap(), server_version.unwrap()),
+        });
+    } else {
+        events::emit(options.log_format, Event::Verification(&report));
+    }
+
+    let mut out = OutFile::create(options.out.as_deref());
+    serde_json::to_writer_pretty(&mut out.writer, &report).unwrap();
+    writeln!(out.writer).unwrap();
+    out.finish();
+
+    verified
+}
+
+/// the periods overlapping with the archive
+fn choose_periods(archive: &[PartialVideoInfo], options: &Options) -> Vec<(DateTime<FixedOffset>, DateTime<FixedOffset>)> {
+    let tz = options.since.timezone();
+    let oldest = archive.iter().map(|video| video.start_time).min();
+    let newest = archive.iter().map(|video| video.start_time).max();
+    let (oldest, newest) = match (oldest, newest) {
+        (Some(oldest), Some(newest)) => (oldest.with_timezone(&tz), newest.with_timezone(&tz)),
+        _ => return Vec::new(),
+    };
+    let until = options.until.unwrap_or_else(|| Utc::now().with_timezone(&tz));
+
+    let mut periods = Vec::new();
+    let mut since = options.since;
+    while since < until && since <= newest {
+        let period_until = std::cmp::min(until, since + options.duration);
+        if oldest < period_until {
+            periods.push((since, period_until));
+        }
+        since = period_until;
+    }
+    periods.shuffle(&mut rand::thread_rng());
+    periods.truncate(options.verify_periods);
+    periods.sort();
+    periods
+}
+
+/// compares the videos got with the archive.
+/// the periods got from other snapshot than the archive are skipped.
+/// the fields not in the archive are not compared.
+fn check(receiver: Receiver<Packet>, archive: &[PartialVideoInfo], version: Option<DateTime<Utc>>) -> VerifyReport {
+    let mut report = VerifyReport {
+        archive_version: version,
+        server_version: None,
+        periods: Vec::new(),
+        skipped: Vec::new(),
+        videos: 0,
+        stale: 0,
+        missing: Vec::new(),
+        extra: Vec::new(),
+        mismatches: Vec::new(),
+    };
+    for packet in receiver.iter() {
+        if Some(packet.last_modified.with_timezone(&Utc)) != version {
+            report.skipped.push(VerifiedPeriod {
+                since: packet.since,
+                until: packet.until,
+                last_modified: packet.last_modified,
+            });
+            continue
+        }
+        let mut in_archive = archive.iter()
+            .filter(|video| packet.since <= video.start_time && video.start_time < packet.until)
+            .map(|video| (video.content_id.as_str(), video))
+            .collect::<HashMap<_, _>>();
+        report.periods.push(VerifiedPeriod {
+            since: packet.since,
+            until: packet.until,
+            last_modified: packet.last_modified,
+        });
+        for (index, got) in packet.videos.iter().enumerate() {
+            let content_id = got.content_id.as_ref().unwrap();
+            let video = match in_archive.remove(content_id.as_str()) {
+                Some(video) => video,
+                None => {
+                    report.missing.push(content_id.clone());
+                    continue
+                }
+            };
+            report.videos += 1;
+            // the counters of the videos got from other snapshot are different
+            if video.last_modified != packet.version_of(index) {
+                report.stale += 1;
+                continue
+            }
+            let mut compare = |field: &'static str, archive: Option<String>, server: String| {
+                match archive {
+                    Some(archive) if archive != server => {
+                        report.mismatches.push(Mismatch { content_id: content_id.clone(), field, archive, server });
+                    }
+                    _ => {}
+                }
+            };
+            compare("title", video.title.clone(), got.title.clone().unwrap_or_default());
+            compare("viewCounter", video.view_counter.map(|x| x.to_string()),
+                    got.view_counter.unwrap_or_default().to_string());
+            compare("mylistCounter", video.mylist_counter.map(|x| x.to_string()),
+                    got.mylist_counter.unwrap_or_default().to_string());
+            compare("commentCounter", video.comment_counter.map(|x| x.to_string()),
+                    got.comment_counter.unwrap_or_default().to_string());
+            compare("lengthSeconds", video.length_seconds.map(|x| x.as_secs().to_string()),
+                    got.length_seconds.unwrap_or_default().as_secs().to_string());
+        }
+        report.extra.extend(in_archive.keys().map(|&content_id| content_id.to_owned()));
+    }
+    report
+}
+
+/// the result of verification
+#[derive(Serialize)]
+pub(crate) struct VerifyReport {
+    // the newest last_modified in the archive
+    archive_version: Option<DateTime<Utc>>,
+    // the snapshot served. nothing is compared if it differs from archive_version
+    server_version: Option<DateTime<Utc>>,
+    periods: Vec<VerifiedPeriod>,
+    // periods got from other snapshot than the archive. the videos are not compared
+    skipped: Vec<VerifiedPeriod>,
+    // videos both in the archive and the server
+    videos: usize,
+    // videos in the archive got from other snapshot. the fields are not compared
+    stale: usize,
+    // videos on the server but not in the archive
+    missing: Vec<String>,
+    // videos in the archive but not on the server
+    extra: Vec<String>,
+    mismatches: Vec<Mismatch>,
+}
+
+#[derive(Serialize)]
+struct VerifiedPeriod {
+    since: DateTime<FixedOffset>,
+    until: DateTime<FixedOffset>,
+    last_modified: DateTime<FixedOffset>,
+}
+
+#[derive(Serialize)]
+struct Mismatch {
+    content_id: String,
+    field: &'static str,
+    archive: String,
+    server: String,
+}
+
+impl VerifyReport {
+    pub(crate) fn is_matched(&self) -> bool {
+        self.missing.is_empty() && self.extra.is_empty() && self.mismatches.is_empty()
+    }
+
+    /// OtherSnapshot if the server serves other snapshot than the newest last_modified in the archive
+    fn verified(&self) -> Verified {
+        match (self.server_version, self.archive_version) {
+            (Some(server_version), Some(version)) if server_version != version => Verified::OtherSnapshot,
+            _ if self.is_matched() => Verified::Matched,
+            _ => Verified::Mismatched,
+        }
+    }
+
+    /// the line for human
+    pub(crate) fn summary(&self) -> String {
+        format!("verify: {} videos in {} periods. {} missing, {} extra, {} mismatches, {} from other snapshot, \
+                 {} periods skipped",
+                self.videos, self.periods.len(), self.missing.len(), self.extra.len(),
+                self.mismatches.len(), self.stale, self.skipped.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use nico_snapshot_api::VideoInfo;
+
+    fn date(value: &str) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339(value).unwrap()
+    }
+
+    const SINCE: &str = "2020-01-01T00:00:00+09:00";
+    const UNTIL: &str = "2020-01-08T00:00:00+09:00";
+    const VERSION: &str = "2020-12-01T05:00:00+09:00";
+
+    fn archived(content_id: &str, views: u32) -> PartialVideoInfo {
+        PartialVideoInfo {
+            last_modified: date(VERSION).with_timezone(&Utc),
+            content_id: content_id.to_owned(),
+            title: Some(content_id.to_owned()),
+            description: None,
+            view_counter: Some(views),
+            mylist_counter: Some(0),
+            length_seconds: Some(std::time::Duration::from_secs(60)),
+            thumbnail_url: None,
+            start_time: date("2020-01-02T00:00:00+09:00").with_timezone(&Utc),
+            last_res_body: None,
+            comment_counter: Some(0),
+            last_comment_time: None,
+            category_tags: None,
+            tags: None,
+            genre: None,
+        }
+    }
+
+    fn got(content_id: &str, views: u32) -> VideoInfo {
+        serde_json::from_value(serde_json::json!({
+            "contentId": content_id,
+            "title": content_id,
+            "viewCounter": views,
+            "mylistCounter": 0,
+            "commentCounter": 0,
+            "lengthSeconds": 60,
+            "startTime": "2020-01-02T00:00:00+09:00",
+        })).unwrap()
+    }
+
+    /// checks the archive against a period got under the snapshot
+    fn check_with(archive: &[PartialVideoInfo], videos: Vec<VideoInfo>, last_modified: &str) -> VerifyReport {
+        let (sender, receiver) = mpsc::channel();
+        sender.send(Packet {
+            since: date(SINCE),
+            until: date(UNTIL),
+            last_modified: date(last_modified),
+            total_count: videos.len(),
+            videos,
+            versions: None,
+            refresh: false,
+        }).unwrap();
+        drop(sender);
+        let version = archive.iter().map(|video| video.last_modified).max();
+        let mut report = check(receiver, archive, version);
+        report.server_version = Some(date(last_modified).with_timezone(&Utc));
+        report
+    }
+
+    #[test]
+    fn matched() {
+        let report = check_with(&[archived("sm1", 10), archived("sm2", 20)],
+                                vec![got("sm1", 10), got("sm2", 20)], VERSION);
+        assert_eq!(report.videos, 2);
+        assert_eq!(report.verified(), Verified::Matched);
+        assert_eq!(report.verified().exit_code(), None);
+    }
+
+    #[test]
+    fn changed_counter() {
+        let report = check_with(&[archived("sm1", 10)], vec![got("sm1", 11)], VERSION);
+        assert_eq!(report.mismatches.len(), 1);
+        assert_eq!((report.mismatches[0].field, report.mismatches[0].archive.as_str(),
+                    report.mismatches[0].server.as_str()), ("viewCounter", "10", "11"));
+        assert_eq!(report.verified().exit_code(), Some(MISMATCH_EXIT_CODE));
+    }
+
+    #[test]
+    fn missing_video() {
+        let report = check_with(&[archived("sm1", 10)], vec![got("sm1", 10), got("sm2", 20)], VERSION);
+        assert_eq!(report.missing, vec!["sm2".to_owned()]);
+        assert_eq!(report.verified(), Verified::Mismatched);
+    }
+
+    #[test]
+    fn extra_video() {
+        let report = check_with(&[archived("sm1", 10), archived("sm2", 20)], vec![got("sm1", 10)], VERSION);
+        assert_eq!(report.extra, vec!["sm2".to_owned()]);
+        assert_eq!(report.verified(), Verified::Mismatched);
+    }
+
+    #[test]
+    fn other_snapshot() {
+        let report = check_with(&[archived("sm1", 10)], vec![got("sm1", 11)], "2020-12-02T05:00:00+09:00");
+        assert_eq!(report.skipped.len(), 1);
+        assert!(report.periods.is_empty() && report.mismatches.is_empty());
+        assert_eq!(report.verified(), Verified::OtherSnapshot);
+        assert_eq!(report.verified().exit_code(), Some(OTHER_SNAPSHOT_EXIT_CODE));
+    }
+}
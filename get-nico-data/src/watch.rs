@@ -0,0 +1,112 @@
+use std::process::Command;
+use chrono::{DateTime, FixedOffset};
+use reqwest::Client;
+use tokio::runtime::Runtime;
+use tokio::time::timeout;
+use nico_snapshot_api::snapshot_version;
+use crate::options::{Options, SNAPSHOT_PLACEHOLDER, SNAPSHOT_NAME_FORMAT};
+use crate::interrupt::{self, is_gave_up, is_interrupted};
+use crate::events::{self, Event};
+use crate::crawl;
+use structs::date_arg::before_now;
+
+/// checks the snapshot version every --watch-interval and gets videos when it's updated.
+/// the current snapshot is got at first. returns when interrupted.
+/// the snapshot a request of which was given up is got again at the next check.
+pub(crate) fn watch(runtime: &mut Runtime, client: &Client, options: &Options) {
+    let mut base = options.base.clone();
+    let mut last_modified = None;
+    while !is_interrupted() {
+        match runtime.block_on(async { timeout(options.retry.timeout, snapshot_version(client)).await }) {
+            Err(err) => events::emit(options.log_format, Event::Error {
+                message: &format!("snapshot version: time out: {}", err),
+            }),
+            Ok(Err(err)) => events::emit(options.log_format, Event::Error {
+                message: &format!("snapshot version: {}", err),
+            }),
+            Ok(Ok(version)) if Some(version.last_modified) == last_modified => {}
+            Ok(Ok(version)) => {
+                let options = for_snapshot(options, version.last_modified, &base);
+                events::emit(options.log_format, Event::Info {
+                    message: &format!("new snapshot {}. getting videos to {}",
+                                      version.last_modified, options.out.as_ref().or(options.out_dir.as_ref()).unwrap()),
+                });
+                crawl(runtime, client, &options);
+                if is_gave_up() {
+                    events::emit(options.log_format, Event::Error {
+                        message: &format!("a request for snapshot {} was given up. getting it again at the next check",
+                                          version.last_modified),
+                    });
+                    interrupt::clear_gave_up();
+                } else if is_interrupted() {
+                    return
+                } else {
+                    if let Some(then) = &options.then {
+                        run_then(then, &options, Some(version.last_modified));
+                    }
+                    if options.incremental {
+                        base = options.out.clone();
+                    }
+                    last_modified = Some(version.last_modified);
+                }
+            }
+        }
+        runtime.block_on(interrupt::delay_for(options.watch_interval));
+    }
+}
+
+/// the options to get videos for the snapshot.
+/// --out, --out-dir, --index, --raw-dir, --report and --validation-report are named by the snapshot date
+/// and `base` is updated. relative --since, --until and --refresh-since are resolved again.
+fn for_snapshot(options: &Options, last_modified: DateTime<FixedOffset>, base: &Option<String>) -> Options {
+    let name = last_modified.with_timezone(&options.since.timezone())
+        .format(SNAPSHOT_NAME_FORMAT)
+        .to_string();
+    let mut options = options.clone();
+    options.out = options.out.map(|out| out.replace(SNAPSHOT_PLACEHOLDER, &name));
+    options.out_dir = options.out_dir.map(|out_dir| out_dir.replace(SNAPSHOT_PLACEHOLDER, &name));
+    options.index = options.index.map(|index| index.replace(SNAPSHOT_PLACEHOLDER, &name));
+    options.raw_dir = options.raw_dir.map(|raw_dir| raw_dir.replace(SNAPSHOT_PLACEHOLDER, &name));
+    options.report = options.report.map(|report| report.replace(SNAPSHOT_PLACEHOLDER, &name));
+    options.validation_report = options.validation_report.map(|report| report.replace(SNAPSHOT_PLACEHOLDER, &name));
+    options.base = base.clone();
+    let tz = options.since.timezone();
+    if let Some(ago) = options.since_ago {
+        options.since = before_now(ago, &tz);
+    }
+    if let Some(ago) = options.until_ago {
+        options.until = Some(before_now(ago, &tz));
+    }
+    if let Some(ago) = options.refresh_since_ago {
+        options.refresh_since = Some(before_now(ago, &tz));
+    }
+    options
+}
+
+/// runs --then command with the shell
+pub(crate) fn run_then(command: &str, options: &Options, last_modified: Option<DateTime<FixedOffset>>) {
+    let mut cmd = if cfg!(windows) {
+        let mut cmd = Command::new("cmd");
+        cmd.args(["/C", command]);
+        cmd
+    } else {
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", command]);
+        cmd
+    };
+    if let Some(out) = options.out.as_ref().or(options.out_dir.as_ref()) {
+        cmd.env("SNAPSHOT_OUT", out);
+    }
+    if let Some(last_modified) = last_modified {
+        cmd.env("SNAPSHOT_LAST_MODIFIED", last_modified.to_rfc3339());
+    }
+    match cmd.status() {
+        Ok(status) if status.success() => {}
+        Ok(status) => events::emit(options.log_format, Event::Error {
+            message: &format!("then: {}: {}", command, status),
+        }),
+        Err(err) => events::emit(options.log_format, Event::Error {
+            message: &format!("then: {}: {}", command, err),
+        }),
+    }
+}
@@ -0,0 +1,30 @@
+use std::collections::HashSet;
+use std::fs::read_to_string;
+use nico_snapshot_api::{EqualFilter, FilterJson};
+use structs::events::LogFormat;
+use crate::events;
+
+/// content ids queried at a request.
+/// the filter is sent in the url so too many ids make the url too long.
+pub(crate) const BATCH_SIZE: usize = 50;
+
+/// reads content ids, one per line like --content-id-out writes.
+/// empty lines are ignored and duplicated ids are removed. exits if the file cannot be read.
+pub(crate) fn read_ids(path: &str, log_format: LogFormat) -> Vec<String> {
+    let text = read_to_string(path)
+        .unwrap_or_else(|err| events::exit_with_error(log_format, &format!("watchlist: {}: {}", path, err)));
+    let mut found = HashSet::new();
+    text.lines()
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .filter(|&id| found.insert(id))
+        .map(ToOwned::to_owned)
+        .collect()
+}
+
+/// the filter matches any of the ids
+pub(crate) fn filter_of(ids: &[String]) -> FilterJson {
+    FilterJson::Or(ids.iter()
+        .map(|id| FilterJson::Equal(EqualFilter::ContentId(id.clone())))
+        .collect())
+}
@@ -1,14 +1,17 @@
-use crate::options::parse_options;
+use crate::options::{parse_options, LogFormat};
 use crate::utils::MyIterUtil;
 use std::fs::{self, File};
-use std::io::{BufReader, BufWriter, Write};
+use std::io::{BufWriter, Write};
 use itertools::Itertools;
 use crate::ymd_print::ymd_to_string;
 use crate::numeral_print::numeral_to_string;
 use indicatif::{ProgressBar, ProgressStyle};
 use crate::index_file::RankingPage;
-use structs::NewVideoInfo;
+use structs::PartialVideoInfo;
+use structs::bin::read_bin;
 use crate::progress_reader::ProgressReader;
+use structs::events::{emit, Event, Phase};
+use std::process::exit;
 
 mod options;
 mod utils;
@@ -17,6 +20,9 @@ mod numeral_print;
 mod index_file;
 mod progress_reader;
 
+/// the fields shown in the pages
+const REQUIRED_FIELDS: &[&str] = &["viewCounter", "lengthSeconds"];
+
 fn main() {
     let options = parse_options();
 
@@ -29,21 +35,33 @@ fn main() {
     let per_page: usize = 200;
     let mut page_number: u64 = 0;
 
-    let progress = ProgressBar::new(input_bin_size);
+    let phase = Phase::start(options.log_format, "reading binary");
+    let progress = new_progress(options.log_format, input_bin_size);
     progress.set_message("reading binary...");
-    progress.enable_steady_tick(10);
-    set_style(&progress);
-    let input_bin = ProgressReader::new(&progress, input_bin);
-
-    let mut input_bin = BufReader::new(input_bin);
-    let list: Vec<NewVideoInfo> = bincode::deserialize_from(&mut input_bin).unwrap();
+    let bin = read_bin(ProgressReader::new(&progress, input_bin)).unwrap_or_else(|err| {
+        progress.finish_and_clear();
+        emit(options.log_format, Event::Error { message: &format!("{}: {}", options.input_bin, err) });
+        exit(1)
+    });
+    if let Some(fields) = bin.fields() {
+        for &field in REQUIRED_FIELDS {
+            if !fields.iter().any(|got| got == field) {
+                progress.finish_and_clear();
+                emit(options.log_format, Event::Error {
+                    message: &format!("{}: {} is required but not got", options.input_bin, field),
+                });
+                exit(1)
+            }
+        }
+    }
+    let list = bin.into_partial_videos();
 
     progress.finish();
     drop(progress);
+    phase.finish();
 
-    let progress = ProgressBar::new(list.len() as u64 / per_page as u64);
-    progress.enable_steady_tick(10);
-    set_style(&progress);
+    let phase = Phase::start(options.log_format, "writing pages");
+    let progress = new_progress(options.log_format, list.len() as u64 / per_page as u64);
 
     for (index, (elements, has_next)) in list.iter()
         .enumerate()
@@ -73,13 +91,16 @@ fn main() {
         }
     }
     progress.finish_with_message("finished");
+    phase.finish();
 
     // write index file
+    let phase = Phase::start(options.log_format, "writing index");
     index_file::index_file(&options.output_dir, &page_infos).unwrap();
+    phase.finish();
 }
 
 fn process_a_chunk<'a, Itr>(versions: Itr, output_dir: &String, info: &PageInfo) -> std::io::Result<u64>
-    where Itr : IntoIterator<Item = (usize, &'a NewVideoInfo)> {
+    where Itr : IntoIterator<Item = (usize, &'a PartialVideoInfo)> {
     let output_html = format!("{}/ranking-{}.html", output_dir, info.page_number);
     let output_html = File::create(output_html)?;
     let mut output_html = BufWriter::new(output_html);
@@ -90,10 +111,10 @@ fn process_a_chunk<'a, Itr>(versions: Itr, output_dir: &String, info: &PageInfo)
 
     for (index, version) in versions {
         let rank = index + 1;
-        let sum_dur = version.view_counter * version.length_seconds;
+        let sum_dur = version.view_counter.unwrap() * version.length_seconds.unwrap();
         let video_id = &version.content_id;
-        let view_count = version.view_counter as u64;
-        let video_length = version.length_seconds;
+        let view_count = version.view_counter.unwrap() as u64;
+        let video_length = version.length_seconds.unwrap();
 
         writeln!(&mut output_html, r#"    <li class="grid-item">"#)?;
         writeln!(&mut output_html, r#"        <div class="ranking-header"><a href="https://nicovideo.jp/watch/{}" class="ranking-header-link">#{}</a></div>"#,
@@ -166,6 +187,18 @@ fn write_prev_cur_next<W: Write>(output_html: &mut W, info: &PageInfo) -> std::i
     Ok(())
 }
 
+fn new_progress(log_format: LogFormat, len: u64) -> ProgressBar {
+    match log_format {
+        LogFormat::Text => {
+            let progress = ProgressBar::new(len);
+            progress.enable_steady_tick(10);
+            set_style(&progress);
+            progress
+        }
+        LogFormat::Json => ProgressBar::hidden(),
+    }
+}
+
 fn set_style(progress: &ProgressBar) {
     progress.set_style(ProgressStyle::default_bar().template("[{elapsed_precise}] {bar:40blue} {pos:>7}/{len:7} ({percent}%) {msg}")
         .progress_chars("##-"));
@@ -1,10 +1,13 @@
 use std::env;
 use std::process::exit;
+pub use structs::events::LogFormat;
 
 pub(crate) fn parse_options() -> Options {
-    let args: Vec<_> = env::args().collect();
+    let mut args: Vec<_> = env::args().collect();
+    let log_format = structs::events::parse_log_format(&mut args);
+    structs::events::report_panics(log_format);
     if args.len() != 3 {
-        eprintln!("{} <input bin> <output dir>", &args[0]);
+        eprintln!("{} [--log-format <text|json>] <input bin> <output dir>", &args[0]);
         exit(-1);
     }
     let input_bin = args[1].to_string();
@@ -13,10 +16,12 @@ pub(crate) fn parse_options() -> Options {
     Options {
         input_bin,
         output_dir: output_html,
+        log_format,
     }
 }
 
 pub struct Options {
     pub input_bin: String,
     pub output_dir: String,
+    pub log_format: LogFormat,
 }
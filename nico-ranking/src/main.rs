@@ -82,22 +82,31 @@ fn main() {
 }
 
 fn run_get_nico_data(options: &Options, work_dir: &Path) {
-    const DATE_FORMAT: &str = "%Y/%m/%d";
-
     println!("running get-nico-data...");
 
     let mut cmd = Command::new(get_exec_path("get-nico-data"));
 
     cmd.current_dir(work_dir);
 
+    // merge-nico-data merges the files in out so the index is written outside of it
+    cmd.args(["--out-dir", "out"]);
+    cmd.args(["--index", "index.json"]);
+    cmd.args(["--timezone", &options.timezone.to_string()]);
+    // relative dates are resolved here so that both are same
     if let Some(since) = options.since {
-        cmd.args(&["--since", &since.format(DATE_FORMAT).to_string()]);
+        cmd.args(["--since", &since.to_rfc3339()]);
     }
     if let Some(until) = options.until {
-        cmd.args(&["--until", &until.format(DATE_FORMAT).to_string()]);
+        cmd.args(["--until", &until.to_rfc3339()]);
     }
     if let Some(duration) = options.duration {
-        cmd.args(&["--duration", &duration_to_string(duration)]);
+        cmd.args(["--duration", &duration_to_string(duration)]);
+    }
+    if let Some(query) = &options.query {
+        cmd.args(["--query", query]);
+    }
+    if let Some(targets) = &options.targets {
+        cmd.args(["--targets", targets]);
     }
     let _temp_file_keeper = if let Some(json) = &options.filter {
         let mut named = NamedTempFile::new().unwrap();
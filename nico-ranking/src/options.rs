@@ -1,5 +1,6 @@
 use clap::*;
-use chrono::{FixedOffset, TimeZone, NaiveDate, Duration, DateTime};
+use chrono::{FixedOffset, Duration, DateTime};
+use structs::date_arg::{parse_timezone, parse_date_time};
 use std::process::exit;
 use nico_snapshot_api::FilterJson;
 use std::io::BufReader;
@@ -15,21 +16,26 @@ macro_rules! exiting_errf {
     })
 }
 
-const DATE_FORMAT_WITH_TIME: &str = "%Y/%m/%d";
-
 pub fn parse_options() -> Options {
-    let jst_timezone = FixedOffset::east(9 * 3600);
     let app = app_from_crate!()
         .arg(Arg::with_name("since")
-            .help("the begin date of find range. defaults the date starts SMILEVIDEO, 2020/03/06")
+            .help("the begin date of find range. RFC 3339, yyyy/mm/dd [HH:MM[:SS]] in --timezone, \
+                   or relative to now like -7d. defaults 2007/03/01 00:00 +09:00, before SMILEVIDEO started")
             .takes_value(true)
+            .allow_hyphen_values(true)
             .short("-s")
             .long("--since"))
         .arg(Arg::with_name("until")
-            .help("the last date of find range. defaults now")
+            .help("the last date of find range in the same format as since. defaults now")
             .takes_value(true)
+            .allow_hyphen_values(true)
             .short("-u")
             .long("--until"))
+        .arg(Arg::with_name("timezone")
+            .help("timezone of since, until and periods. UTC or offset like +09:00. defaults +09:00")
+            .takes_value(true)
+            .allow_hyphen_values(true)
+            .long("--timezone"))
         .arg(Arg::with_name("duration")
             .help("duration to be got at a time. defaults 1 week")
             .takes_value(true)
@@ -41,6 +47,17 @@ pub fn parse_options() -> Options {
             .takes_value(true)
             .short("-f")
             .long("--filter"))
+        .arg(Arg::with_name("query")
+            .help("keyword to search videos. all videos are ranked if not specified")
+            .takes_value(true)
+            .short("-q")
+            .long("--query"))
+        .arg(Arg::with_name("targets")
+            .help("comma separated fields to search --query in. title, description, tags or tagsExact. \
+                   defaults title,description,tags")
+            .takes_value(true)
+            .long("--targets")
+            .requires("query"))
         .arg(Arg::with_name("ranking_type")
             .help("type of ranking")
             .possible_values(&["watch-sum", "watch-cnt", "watch-lng"])
@@ -57,19 +74,18 @@ pub fn parse_options() -> Options {
         ;
     let matches = app.get_matches();
 
+    let timezone = matches.value_of("timezone")
+        .map(|timezone| parse_timezone(timezone)
+            .unwrap_or_else(|err| exiting_errf!("timezone: {}", err)))
+        .unwrap_or_else(|| FixedOffset::east_opt(9 * 3600).unwrap());
+
     let since = matches.value_of("since")
-        .map(|date| jst_timezone
-            .from_local_date(&NaiveDate::parse_from_str(date, DATE_FORMAT_WITH_TIME)
-                .unwrap_or_else(|err| exiting_errf!("since: {}", err)))
-            .unwrap()
-            .and_hms(0, 0, 0));
+        .map(|date| parse_date_time(date, &timezone)
+            .unwrap_or_else(|err| exiting_errf!("since: {}", err)));
 
     let until = matches.value_of("until")
-        .map(|date| jst_timezone
-            .from_local_date(&NaiveDate::parse_from_str(date, DATE_FORMAT_WITH_TIME)
-                .unwrap_or_else(|err| exiting_errf!("until: {}", err)))
-            .unwrap()
-            .and_hms(0, 0, 0));
+        .map(|date| parse_date_time(date, &timezone)
+            .unwrap_or_else(|err| exiting_errf!("until: {}", err)));
 
     let duration = matches.value_of("duration")
         .map(|duration| Duration::from_std(parse_duration::parse(duration)
@@ -83,26 +99,36 @@ pub fn parse_options() -> Options {
                 .unwrap_or_else(|err| exiting_errf!("filter: {}", err))
         });
 
+    let query = matches.value_of("query").map(|x| x.to_owned());
+
+    let targets = matches.value_of("targets").map(|x| x.to_owned());
+
     let ranking_type = matches.value_of("ranking_type").unwrap().to_string();
 
     let phase_since = matches.value_of("phase_since")
         .map_or(Phase::GetNicoData, |str| Phase::from_str(str).unwrap());
 
     Options {
+        timezone,
         since,
         until,
         duration,
         filter,
+        query,
+        targets,
         ranking_type,
         phase_since,
     }
 }
 
 pub struct Options {
+    pub timezone: FixedOffset,
     pub since: Option<DateTime<FixedOffset>>,
     pub until: Option<DateTime<FixedOffset>>,
     pub duration: Option<Duration>,
     pub filter: Option<FilterJson>,
+    pub query: Option<String>,
+    pub targets: Option<String>,
     pub ranking_type: String,
     pub phase_since: Phase,
 }
@@ -0,0 +1,126 @@
+use std::fmt::{Display, Formatter};
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use reqwest::{Response, StatusCode};
+use reqwest::header::RETRY_AFTER;
+
+/// error of requests to the api.
+/// keeps Retry-After header if the server responded with error status.
+#[derive(Debug)]
+pub struct RequestError {
+    inner: Inner,
+    retry_after: Option<Duration>,
+}
+
+#[derive(Debug)]
+enum Inner {
+    Http(reqwest::Error),
+    // the body got by get_raw is not a valid response
+    Json(serde_json::Error),
+}
+
+impl RequestError {
+    pub fn status(&self) -> Option<StatusCode> {
+        match &self.inner {
+            Inner::Http(inner) => inner.status(),
+            Inner::Json(_) => None,
+        }
+    }
+
+    /// the duration to wait before retrying requested by the server
+    pub fn retry_after(&self) -> Option<Duration> {
+        self.retry_after
+    }
+}
+
+impl From<reqwest::Error> for RequestError {
+    fn from(inner: reqwest::Error) -> Self {
+        RequestError {
+            inner: Inner::Http(inner),
+            retry_after: None,
+        }
+    }
+}
+
+impl From<serde_json::Error> for RequestError {
+    fn from(inner: serde_json::Error) -> Self {
+        RequestError {
+            inner: Inner::Json(inner),
+            retry_after: None,
+        }
+    }
+}
+
+impl Display for RequestError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.inner {
+            Inner::Http(inner) => Display::fmt(inner, f),
+            Inner::Json(inner) => Display::fmt(inner, f),
+        }
+    }
+}
+
+impl std::error::Error for RequestError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.inner {
+            Inner::Http(inner) => Some(inner),
+            Inner::Json(inner) => Some(inner),
+        }
+    }
+}
+
+/// same as Response::error_for_status but keeps Retry-After header
+pub(crate) fn error_for_status(response: Response) -> Result<Response, RequestError> {
+    let retry_after = response.headers().get(RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(parse_retry_after);
+    response.error_for_status().map_err(|inner| RequestError {
+        inner: Inner::Http(inner),
+        retry_after,
+    })
+}
+
+/// Retry-After is either seconds or http date
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    if let Ok(seconds) = value.trim().parse::<u64>() {
+        return Some(Duration::from_secs(seconds))
+    }
+    let date = DateTime::parse_from_rfc2822(value.trim()).ok()?;
+    Some((date.with_timezone(&Utc) - Utc::now()).to_std().unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_after_seconds() {
+        assert_eq!(parse_retry_after("120"), Some(Duration::from_secs(120)));
+        assert_eq!(parse_retry_after(" 0 "), Some(Duration::from_secs(0)));
+    }
+
+    #[test]
+    fn retry_after_http_date() {
+        let date = (Utc::now() + chrono::Duration::seconds(120)).to_rfc2822();
+        let wait = parse_retry_after(&date).unwrap();
+        assert!(Duration::from_secs(115) <= wait && wait <= Duration::from_secs(120), "{:?}", wait);
+
+        // the format servers send
+        let date = (Utc::now() + chrono::Duration::seconds(120)).format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+        let wait = parse_retry_after(&date).unwrap();
+        assert!(Duration::from_secs(115) <= wait && wait <= Duration::from_secs(120), "{:?}", wait);
+    }
+
+    #[test]
+    fn retry_after_past_date() {
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"), Some(Duration::from_secs(0)));
+    }
+
+    #[test]
+    fn invalid_retry_after() {
+        assert_eq!(parse_retry_after(""), None);
+        assert_eq!(parse_retry_after("-1"), None);
+        assert_eq!(parse_retry_after("1.5"), None);
+        assert_eq!(parse_retry_after("tomorrow"), None);
+    }
+}
@@ -95,6 +95,7 @@ filters! {
         LastCommentTime ("lastCommentTime", last_comment_time, DateTime<FixedOffset>),
     },
     {
+        ContentId ("contentId", content_id, String),
         CategoryTags ("categoryTags", category_tags, String),
         Tags ("tags", tags, String),
         Genre ("genre", genre, String),
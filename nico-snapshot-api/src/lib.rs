@@ -2,7 +2,9 @@ mod filter_json;
 mod query_params;
 mod response;
 mod serializers;
+mod error;
 
+pub use error::RequestError;
 pub use filter_json::*;
 pub use query_params::*;
 pub use response::*;
@@ -17,6 +19,6 @@ pub struct SnapshotVersion {
     pub last_modified: DateTime<FixedOffset>
 }
 
-pub async fn snapshot_version(client: &Client) -> reqwest::Result<SnapshotVersion> {
-    Ok(client.get(SEARCH_SNAPSHOT_V2_VERSION).send().await?.json().await?)
+pub async fn snapshot_version(client: &Client) -> Result<SnapshotVersion, RequestError> {
+    Ok(error::error_for_status(client.get(SEARCH_SNAPSHOT_V2_VERSION).send().await?)?.json().await?)
 }
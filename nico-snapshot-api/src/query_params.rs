@@ -5,6 +5,9 @@ use std::fmt::{Display, Formatter};
 use std::str::FromStr;
 use super::response::ResponseJson;
 use super::serializers;
+use super::error::{self, RequestError};
+
+const SEARCH_SNAPSHOT_V2_ENDPOINT: &str = "https://api.search.nicovideo.jp/api/v2/snapshot/video/contents/search";
 
 #[derive(Serialize, Eq, PartialEq, Debug, Clone)]
 pub struct QueryParams {
@@ -91,16 +94,27 @@ impl QueryParams {
         self.context = Some(context.to_owned());
     }
 
-    pub async fn get(&self, client: &reqwest::Client) -> reqwest::Result<ResponseJson> {
-        const SEARCH_SNAPSHOT_V2_ENDPOINT: &str = "https://api.search.nicovideo.jp/api/v2/snapshot/video/contents/search";
-        Ok(client.get(SEARCH_SNAPSHOT_V2_ENDPOINT)
+    pub async fn get(&self, client: &reqwest::Client) -> Result<ResponseJson, RequestError> {
+        let response = client.get(SEARCH_SNAPSHOT_V2_ENDPOINT)
             .query(&self)
             .send()
-            .await?
-            .error_for_status()?
+            .await?;
+        Ok(error::error_for_status(response)?
             .json()
             .await?)
     }
+
+    /// same as get but the body is returned too so that it can be stored as is
+    pub async fn get_raw(&self, client: &reqwest::Client) -> Result<(ResponseJson, String), RequestError> {
+        let response = client.get(SEARCH_SNAPSHOT_V2_ENDPOINT)
+            .query(&self)
+            .send()
+            .await?;
+        let text = error::error_for_status(response)?
+            .text()
+            .await?;
+        Ok((serde_json::from_str(&text)?, text))
+    }
 }
 
 fn is_zero(v: &u32) -> bool {
@@ -30,6 +30,7 @@ pub struct VideoInfo {
     pub mylist_counter: Option<u32>,
     #[serde(with="serializers::duration_opt_seconds")]
     #[serde(rename="lengthSeconds")]
+    #[serde(default)]
     pub length_seconds: Option<Duration>,
     #[serde(rename="thumbnailUrl")]
     pub thumbnail_url: Option<String>,
@@ -44,6 +45,7 @@ pub struct VideoInfo {
     #[serde(rename="categoryTags")]
     pub category_tags: Option<String>,
     #[serde(with="serializers::space_string_vec_opt")]
+    #[serde(default)]
     pub tags: Option<Vec<String>>,
     pub genre: Option<String>,
 }
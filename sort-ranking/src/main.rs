@@ -1,12 +1,14 @@
-use crate::options::{parse_options, RankingType};
+use crate::options::{parse_options, RankingType, LogFormat};
 use std::fs::{File};
-use std::io::{BufReader, BufWriter, Read};
-use structs::{NewVideoInfo};
+use std::io::{BufWriter, Read};
+use structs::PartialVideoInfo;
+use structs::bin::{read_bin, write_full, write_partial, Bin};
 use rayon::prelude::*;
-use std::time::Instant;
 use indicatif::{ProgressBar, ProgressStyle};
 use crate::progress_reader::ProgressReader;
 use std::cmp::Ordering;
+use structs::events::{emit, Event, Phase};
+use std::process::exit;
 
 mod options;
 mod option_expr_parser;
@@ -15,45 +17,73 @@ mod progress_reader;
 fn main() {
     let options = parse_options();
 
-    let start = Instant::now();
-    eprintln!("reading file...");
+    let phase = Phase::start(options.log_format, "reading file");
     let input_bin_size = std::fs::metadata(&options.input_bin).unwrap().len();
     let mut input_bin = File::open(&options.input_bin).unwrap();
-    let mut videos: Vec<NewVideoInfo> = get_videos(&mut input_bin, input_bin_size);
+    let bin = get_videos(&options.input_bin, &mut input_bin, input_bin_size, options.log_format);
+    // None if all fields are got
+    let fields = bin.fields().map(|fields| fields.to_vec());
+    if let Some(fields) = &fields {
+        let mut required = fields_of(&options.ranking_type).to_vec();
+        if options.filter.is_some() {
+            required.push("tags");
+        }
+        for field in required {
+            if !fields.iter().any(|got| got == field) {
+                emit(options.log_format, Event::Error {
+                    message: &format!("{}: {} is required but not got", options.input_bin, field),
+                });
+                exit(1)
+            }
+        }
+    }
+    let mut videos = bin.into_partial_videos();
     let key_gen = key_generator_of(options.ranking_type);
-    eprintln!("reading file took {}s", (Instant::now() - start).as_secs_f64());
+    phase.finish();
 
     if let Some(filter) = options.filter {
-        let start = Instant::now();
-        eprintln!("filtering...");
+        let phase = Phase::start(options.log_format, "filtering");
 
         videos.retain(filter);
 
-        eprintln!("filtering took {}s", (Instant::now() - start).as_secs_f64());
+        phase.finish();
     }
 
-    let start = Instant::now();
-    eprintln!("sorting...");
+    let phase = Phase::start(options.log_format, "sorting");
     videos.par_sort_by_key(|data| key_gen(data).reversing());
-    eprintln!("sorting took {}s", (Instant::now() - start).as_secs_f64());
+    phase.finish();
 
-    let start = Instant::now();
-    eprintln!("writing...");
-    let output_file = File::create(options.output_bin).unwrap();
+    let phase = Phase::start(options.log_format, "writing");
+    let output_file = File::create(&options.output_bin).unwrap();
     let mut output_file = BufWriter::new(output_file);
-    bincode::serialize_into(&mut output_file, &videos).unwrap();
-    eprintln!("writing {}s", (Instant::now() - start).as_secs_f64());
+    // written in the same format as the input
+    let written = match &fields {
+        None => write_full(&mut output_file, &videos),
+        Some(fields) => write_partial(&mut output_file, fields, &videos),
+    };
+    if let Err(err) = written {
+        emit(options.log_format, Event::Error { message: &format!("{}: {}", options.output_bin, err) });
+        exit(1)
+    }
+    phase.finish();
 }
 
-fn get_videos<R: Read>(input_bin: R, input_bin_size: u64) -> Vec<NewVideoInfo> {
-    let progress = ProgressBar::new(input_bin_size);
+fn get_videos<R: Read>(name: &str, input_bin: R, input_bin_size: u64, log_format: LogFormat) -> Bin {
+    let progress = match log_format {
+        LogFormat::Text => ProgressBar::new(input_bin_size),
+        LogFormat::Json => ProgressBar::hidden(),
+    };
     progress.set_message("reading binary...");
-    progress.enable_steady_tick(10);
+    if log_format == LogFormat::Text {
+        progress.enable_steady_tick(10);
+    }
     set_style(&progress);
-    let mut input_bin = ProgressReader::new(&progress, input_bin);
-    let mut input_bin = BufReader::new(input_bin);
 
-    return bincode::deserialize_from(&mut input_bin).unwrap();
+    return read_bin(ProgressReader::new(&progress, input_bin)).unwrap_or_else(|err| {
+        progress.finish_and_clear();
+        emit(log_format, Event::Error { message: &format!("{}: {}", name, err) });
+        exit(1)
+    });
 }
 
 fn set_style(progress: &ProgressBar) {
@@ -61,16 +91,26 @@ fn set_style(progress: &ProgressBar) {
         .progress_chars("##-"));
 }
 
-fn key_generator_of(for_type: RankingType) -> Box<dyn Fn(&NewVideoInfo) -> u64 + Sync> {
+/// the fields used to sort
+fn fields_of(for_type: &RankingType) -> &'static [&'static str] {
+    match for_type {
+        RankingType::WatchSum => &["lengthSeconds", "viewCounter"],
+        RankingType::WatchCnt => &["viewCounter"],
+        RankingType::WatchLng => &["lengthSeconds"],
+    }
+}
+
+/// the fields of fields_of are got
+fn key_generator_of(for_type: RankingType) -> Box<dyn Fn(&PartialVideoInfo) -> u64 + Sync> {
     match for_type {
         RankingType::WatchSum => Box::new(|data| {
-            data.length_seconds.as_secs() * data.view_counter as u64
+            data.length_seconds.unwrap().as_secs() * data.view_counter.unwrap() as u64
         }),
         RankingType::WatchCnt => Box::new(|data| {
-            data.view_counter as u64
+            data.view_counter.unwrap() as u64
         }),
         RankingType::WatchLng => Box::new(|data| {
-            data.length_seconds.as_secs()
+            data.length_seconds.unwrap().as_secs()
         }),
     }
 }
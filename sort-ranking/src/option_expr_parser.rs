@@ -1,6 +1,7 @@
-use structs::NewVideoInfo;
+use structs::PartialVideoInfo;
 
-pub type Filter = Box<dyn Fn(&NewVideoInfo) -> bool>;
+/// tags must be got
+pub type Filter = Box<dyn Fn(&PartialVideoInfo) -> bool>;
 
 pub fn parse<'a, I: 'a + Iterator<Item = &'a str>>(iter: &mut I) -> Option<Filter> {
     let filter = parse_expr(iter);
@@ -18,7 +19,7 @@ fn parse_expr<'a, I: 'a + Iterator<Item = &'a str>>(iter: &mut I) -> Option<Filt
         }
         Some("in_tags") => {
             let tag = iter.next().expect("tag name" as &'static str).to_string();
-            Box::new(move |x| x.tags.iter().any(|x| x.as_str() == tag.as_str()))
+            Box::new(move |x| x.tags.as_ref().unwrap().iter().any(|x| x.as_str() == tag.as_str()))
         }
         Some(key) => panic!("unknown keyword: {}", key),
         None => return None
@@ -1,11 +1,15 @@
 use std::env;
 use std::process::exit;
+pub use structs::events::LogFormat;
 use crate::option_expr_parser::{self, Filter};
 
 pub(crate) fn parse_options() -> Options {
-    let args: Vec<_> = env::args().collect();
+    let mut args: Vec<_> = env::args().collect();
+    let log_format = structs::events::parse_log_format(&mut args);
+    // installed before the expressions are parsed since invalid ones panic
+    structs::events::report_panics(log_format);
     if args.len() <= 4 {
-        eprintln!("{} <input bin> <output bin> <ranking-type> [expressions]", &args[0]);
+        eprintln!("{} [--log-format <text|json>] <input bin> <output bin> <ranking-type> [expressions]", &args[0]);
         exit(-1);
     }
     let input_bin = args[1].to_string();
@@ -29,6 +33,7 @@ pub(crate) fn parse_options() -> Options {
         output_bin,
         ranking_type,
         filter,
+        log_format,
     }
 }
 
@@ -37,8 +42,10 @@ pub struct Options {
     pub output_bin: String,
     pub ranking_type: RankingType,
     pub filter: Option<Filter>,
+    pub log_format: LogFormat,
 }
 
+
 pub enum RankingType {
     WatchSum,
     WatchCnt,
@@ -0,0 +1,185 @@
+use std::io::{BufReader, Cursor, Read, Write};
+use serde::{Deserialize, Serialize};
+use crate::{NewVideoInfo, PartialVideoInfo};
+use crate::compression::decompressing;
+
+/// magic number at the head of the bin of PartialBin.
+/// the bin of Vec<NewVideoInfo> starts with the count of videos which never be this.
+const PARTIAL_MAGIC: [u8; 8] = *b"NICOPART";
+
+/// the videos in a bin
+pub enum Bin {
+    /// got with all fields
+    Full(Vec<NewVideoInfo>),
+    /// got with --fields
+    Partial(PartialBin),
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PartialBin {
+    /// the names of the fields got in the snapshot api like viewCounter
+    pub fields: Vec<String>,
+    pub videos: Vec<PartialVideoInfo>,
+}
+
+#[derive(Serialize)]
+struct PartialBinRef<'a> {
+    fields: &'a [String],
+    videos: &'a [PartialVideoInfo],
+}
+
+impl Bin {
+    /// the names of the fields got. None if all fields are got
+    pub fn fields(&self) -> Option<&[String]> {
+        match self {
+            Bin::Full(_) => None,
+            Bin::Partial(bin) => Some(&bin.fields),
+        }
+    }
+
+    /// the videos if got with all fields
+    pub fn into_full(self) -> Option<Vec<NewVideoInfo>> {
+        match self {
+            Bin::Full(videos) => Some(videos),
+            Bin::Partial(_) => None,
+        }
+    }
+
+    /// the videos and the fields if got with --fields
+    pub fn into_partial(self) -> Option<PartialBin> {
+        match self {
+            Bin::Full(_) => None,
+            Bin::Partial(bin) => Some(bin),
+        }
+    }
+
+    pub fn into_partial_videos(self) -> Vec<PartialVideoInfo> {
+        match self {
+            Bin::Full(videos) => videos.into_iter().map(PartialVideoInfo::from).collect(),
+            Bin::Partial(bin) => bin.videos,
+        }
+    }
+}
+
+impl PartialBin {
+    pub fn has_field(&self, name: &str) -> bool {
+        self.fields.iter().any(|field| field == name)
+    }
+}
+
+/// reads the bin of either Vec<NewVideoInfo> or PartialBin. the bin compressed with zstd is decompressed.
+pub fn read_bin<'a>(reader: impl Read + 'a) -> bincode::Result<Bin> {
+    let mut reader = BufReader::new(decompressing(reader)?);
+    let mut head = [0u8; 8];
+    reader.read_exact(&mut head)?;
+    if head == PARTIAL_MAGIC {
+        Ok(Bin::Partial(bincode::deserialize_from(reader)?))
+    } else {
+        Ok(Bin::Full(bincode::deserialize_from(Cursor::new(head).chain(reader))?))
+    }
+}
+
+/// writes the videos as Vec<NewVideoInfo>. all fields must be got.
+/// written one by one to not copy all videos. fails at the first video some fields of which are not got.
+pub fn write_full(mut writer: impl Write, videos: &[PartialVideoInfo]) -> bincode::Result<()> {
+    bincode::serialize_into(&mut writer, &(videos.len() as u64))?;
+    for video in videos {
+        let full = video.to_full().ok_or_else(|| bincode::ErrorKind::Custom(
+            format!("{}: written as NewVideoInfo but some fields are not got", video.content_id)))?;
+        bincode::serialize_into(&mut writer, &full)?;
+    }
+    Ok(())
+}
+
+/// writes the videos as PartialBin
+pub fn write_partial(mut writer: impl Write, fields: &[String], videos: &[PartialVideoInfo]) -> bincode::Result<()> {
+    writer.write_all(&PARTIAL_MAGIC)?;
+    bincode::serialize_into(writer, &PartialBinRef { fields, videos })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    fn video(content_id: &str) -> PartialVideoInfo {
+        PartialVideoInfo {
+            last_modified: Utc.timestamp_opt(1_600_000_000, 0).unwrap(),
+            content_id: content_id.to_owned(),
+            title: Some(content_id.to_owned()),
+            description: None,
+            view_counter: Some(10),
+            mylist_counter: Some(1),
+            length_seconds: Some(std::time::Duration::from_secs(60)),
+            thumbnail_url: None,
+            start_time: Utc.timestamp_opt(1_500_000_000, 0).unwrap(),
+            last_res_body: None,
+            comment_counter: Some(2),
+            last_comment_time: None,
+            category_tags: None,
+            tags: Some(vec!["tag".to_owned()]),
+            genre: None,
+        }
+    }
+
+    #[test]
+    fn full_is_vec_of_new_video_info() {
+        let mut bytes = Vec::new();
+        write_full(&mut bytes, &[video("sm1"), video("sm2")]).unwrap();
+        let videos: Vec<NewVideoInfo> = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(videos.iter().map(|video| video.content_id.as_str()).collect::<Vec<_>>(), vec!["sm1", "sm2"]);
+
+        assert_eq!(read_bin(bytes.as_slice()).unwrap().into_full().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn full_requires_all_fields() {
+        let mut projected = video("sm1");
+        projected.title = None;
+        let err = write_full(&mut Vec::new(), &[video("sm0"), projected]).err().unwrap();
+        assert!(err.to_string().starts_with("sm1: "), "{}", err);
+    }
+
+    #[test]
+    fn partial() {
+        let mut projected = video("sm1");
+        projected.title = None;
+        projected.tags = None;
+        let fields = vec!["contentId".to_owned(), "startTime".to_owned(), "viewCounter".to_owned()];
+        let mut bytes = Vec::new();
+        write_partial(&mut bytes, &fields, &[projected]).unwrap();
+
+        let bin = read_bin(bytes.as_slice()).unwrap();
+        assert_eq!(bin.fields(), Some(fields.as_slice()));
+        let videos = bin.into_partial_videos();
+        assert_eq!(videos.len(), 1);
+        assert_eq!(videos[0].view_counter, Some(10));
+        assert!(videos[0].title.is_none() && videos[0].tags.is_none());
+        assert!(videos[0].to_full().is_none());
+    }
+
+    #[test]
+    fn compressed() {
+        let fields = vec!["contentId".to_owned(), "startTime".to_owned()];
+        let mut bytes = Vec::new();
+        write_partial(&mut bytes, &fields, &[video("sm1")]).unwrap();
+        let compressed = zstd::encode_all(bytes.as_slice(), 0).unwrap();
+        let bin = read_bin(compressed.as_slice()).unwrap().into_partial().unwrap();
+        assert!(bin.has_field("startTime") && !bin.has_field("title"));
+        assert_eq!(bin.videos[0].content_id, "sm1");
+    }
+
+    #[test]
+    fn broken_zstd() {
+        let mut compressed = zstd::encode_all(&b"NICOPART"[..], 0).unwrap();
+        compressed.truncate(6);
+        assert!(read_bin(compressed.as_slice()).is_err());
+    }
+
+    #[test]
+    fn empty() {
+        let mut bytes = Vec::new();
+        write_full(&mut bytes, &[]).unwrap();
+        assert!(read_bin(bytes.as_slice()).unwrap().into_partial_videos().is_empty());
+    }
+}
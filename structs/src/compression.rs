@@ -0,0 +1,15 @@
+use std::io::{self, BufRead, BufReader, Read};
+
+/// magic number at the head of zstd frames
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xB5, 0x2F, 0xFD];
+
+/// the reader of the bin which decompresses it if it's compressed with zstd.
+/// pass ProgressReader as inner to show progress against the compressed size.
+pub fn decompressing<'a, R: Read + 'a>(inner: R) -> io::Result<Box<dyn Read + 'a>> {
+    let mut inner = BufReader::new(inner);
+    if inner.fill_buf()?.starts_with(&ZSTD_MAGIC) {
+        Ok(Box::new(zstd::Decoder::with_buffer(inner)?))
+    } else {
+        Ok(Box::new(inner))
+    }
+}
@@ -0,0 +1,172 @@
+use chrono::{DateTime, Duration, FixedOffset, NaiveDate, NaiveDateTime, TimeZone, Timelike, Utc};
+
+const DATE_FORMAT: &str = "%Y/%m/%d";
+const DATE_TIME_FORMATS: &[&str] = &["%Y/%m/%d %H:%M:%S", "%Y/%m/%d %H:%M"];
+
+/// parses timezone. either UTC or an offset like +09:00
+pub fn parse_timezone(value: &str) -> Result<FixedOffset, String> {
+    if value.eq_ignore_ascii_case("utc") || value == "Z" {
+        return Ok(FixedOffset::east_opt(0).unwrap())
+    }
+    let invalid = || format!("expected UTC or offset like +09:00 but was {}", value);
+    let (sign, rest) = match value.as_bytes().first() {
+        Some(b'+') => (1, &value[1..]),
+        Some(b'-') => (-1, &value[1..]),
+        _ => return Err(invalid()),
+    };
+    if !rest.is_ascii() {
+        return Err(invalid())
+    }
+    // HH, HHMM or HH:MM
+    let (hours, minutes) = match (rest.len(), rest.as_bytes().get(2)) {
+        (2, None) => (rest, "00"),
+        (4, _) => (&rest[..2], &rest[2..]),
+        (5, Some(b':')) => (&rest[..2], &rest[3..]),
+        _ => return Err(invalid()),
+    };
+    if !hours.bytes().chain(minutes.bytes()).all(|c| c.is_ascii_digit()) {
+        return Err(invalid())
+    }
+    let hours = hours.parse::<i32>().unwrap();
+    let minutes = minutes.parse::<i32>().unwrap();
+    if hours > 23 || minutes > 59 {
+        return Err(invalid())
+    }
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60)).ok_or_else(invalid)
+}
+
+/// parses date and time in the timezone. accepts
+/// - RFC 3339 like 2020-03-06T12:00:00+09:00
+/// - yyyy/mm/dd, yyyy/mm/dd HH:MM or yyyy/mm/dd HH:MM:SS in the timezone
+/// - relative to now like -7d or -12h
+pub fn parse_date_time(value: &str, timezone: &FixedOffset) -> Result<DateTime<FixedOffset>, String> {
+    if let Some(duration) = parse_relative(value) {
+        return Ok(before_now(duration?, timezone))
+    }
+    if let Ok(date_time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(date_time.with_timezone(timezone))
+    }
+    let local = DATE_TIME_FORMATS.iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .or_else(|| NaiveDate::parse_from_str(value, DATE_FORMAT).ok()
+            .map(|date| date.and_hms_opt(0, 0, 0).unwrap()))
+        .ok_or_else(|| format!("expected RFC 3339, yyyy/mm/dd [HH:MM[:SS]] or -<duration> but was {}", value))?;
+    Ok(timezone.from_local_datetime(&local).unwrap())
+}
+
+/// parses relative date like -7d into the duration before now. None if not relative
+pub fn parse_relative(value: &str) -> Option<Result<Duration, String>> {
+    let relative = value.strip_prefix('-')?;
+    Some(parse_duration::parse(relative)
+        .map_err(|err| err.to_string())
+        .and_then(|duration| Duration::from_std(duration).map_err(|err| err.to_string())))
+}
+
+/// the time the duration before now in the timezone
+pub fn before_now(duration: Duration, timezone: &FixedOffset) -> DateTime<FixedOffset> {
+    let now = Utc::now().with_nanosecond(0).unwrap();
+    (now - duration).with_timezone(timezone)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn jst() -> FixedOffset {
+        FixedOffset::east_opt(9 * 3600).unwrap()
+    }
+
+    #[test]
+    fn timezone() {
+        assert_eq!(parse_timezone("UTC"), Ok(FixedOffset::east_opt(0).unwrap()));
+        assert_eq!(parse_timezone("utc"), Ok(FixedOffset::east_opt(0).unwrap()));
+        assert_eq!(parse_timezone("Z"), Ok(FixedOffset::east_opt(0).unwrap()));
+        assert_eq!(parse_timezone("+09:00"), Ok(jst()));
+        assert_eq!(parse_timezone("+0900"), Ok(jst()));
+        assert_eq!(parse_timezone("+09"), Ok(jst()));
+        assert_eq!(parse_timezone("-05:30"), Ok(FixedOffset::west_opt(5 * 3600 + 30 * 60).unwrap()));
+    }
+
+    #[test]
+    fn invalid_timezone() {
+        assert!(parse_timezone("").is_err());
+        assert!(parse_timezone("JST").is_err());
+        assert!(parse_timezone("09:00").is_err());
+        assert!(parse_timezone("+9").is_err());
+        assert!(parse_timezone("+09:0a").is_err());
+        assert!(parse_timezone("+24:00").is_err());
+        assert!(parse_timezone("+0:900").is_err());
+        assert!(parse_timezone("+09:000").is_err());
+        assert!(parse_timezone("+0あ").is_err());
+    }
+
+    #[test]
+    fn minutes_above_59() {
+        assert!(parse_timezone("+09:60").is_err());
+        assert!(parse_timezone("+09:99").is_err());
+        assert!(parse_timezone("-0999").is_err());
+        assert_eq!(parse_timezone("+09:59"), Ok(FixedOffset::east_opt(9 * 3600 + 59 * 60).unwrap()));
+    }
+
+    #[test]
+    fn out_of_range_offset() {
+        assert!(parse_timezone("+25:00").is_err());
+        assert!(parse_timezone("-99:00").is_err());
+        assert!(parse_timezone("+99").is_err());
+        assert!(parse_timezone("-9999").is_err());
+        assert_eq!(parse_timezone("-23:59"), Ok(FixedOffset::west_opt(23 * 3600 + 59 * 60).unwrap()));
+    }
+
+    #[test]
+    fn date_in_timezone() {
+        let expected = DateTime::parse_from_rfc3339("2020-03-06T00:00:00+09:00").unwrap();
+        let got = parse_date_time("2020/03/06", &jst()).unwrap();
+        assert_eq!(got, expected);
+        assert_eq!(got.offset(), &jst());
+    }
+
+    #[test]
+    fn date_time_in_timezone() {
+        assert_eq!(parse_date_time("2020/03/06 12:34", &jst()),
+                   Ok(DateTime::parse_from_rfc3339("2020-03-06T12:34:00+09:00").unwrap()));
+        assert_eq!(parse_date_time("2020/03/06 12:34:56", &jst()),
+                   Ok(DateTime::parse_from_rfc3339("2020-03-06T12:34:56+09:00").unwrap()));
+    }
+
+    #[test]
+    fn rfc3339_converted_to_timezone() {
+        let got = parse_date_time("2020-03-06T03:00:00Z", &jst()).unwrap();
+        assert_eq!(got, DateTime::parse_from_rfc3339("2020-03-06T12:00:00+09:00").unwrap());
+        assert_eq!(got.offset(), &jst());
+    }
+
+    #[test]
+    fn relative_to_now() {
+        let before = Utc::now().with_nanosecond(0).unwrap();
+        let got = parse_date_time("-7d", &jst()).unwrap();
+        let after = Utc::now();
+        assert_eq!(got.offset(), &jst());
+        assert!(before - Duration::days(7) <= got && got <= after - Duration::days(7));
+
+        let got = parse_date_time("-12h", &jst()).unwrap();
+        assert!(got <= Utc::now() - Duration::hours(12));
+    }
+
+    #[test]
+    fn relative() {
+        assert_eq!(parse_relative("-7d"), Some(Ok(Duration::days(7))));
+        assert_eq!(parse_relative("-90min"), Some(Ok(Duration::minutes(90))));
+        assert_eq!(parse_relative("2020/03/06"), None);
+        assert!(matches!(parse_relative("-seven days"), Some(Err(_))));
+    }
+
+    #[test]
+    fn invalid_date_time() {
+        assert!(parse_date_time("", &jst()).is_err());
+        assert!(parse_date_time("2020-03-06", &jst()).is_err());
+        assert!(parse_date_time("2020/13/01", &jst()).is_err());
+        assert!(parse_date_time("2020/03/06 25:00", &jst()).is_err());
+        assert!(parse_date_time("yesterday", &jst()).is_err());
+        assert!(parse_date_time("-seven days", &jst()).is_err());
+    }
+}
@@ -0,0 +1,160 @@
+use std::time::Instant;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// format of log to stderr
+#[derive(Eq, PartialEq, Copy, Clone)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+/// the events of sort-ranking and html-gen written to stderr as a line of json in json log format
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    PhaseStart {
+        phase: &'a str,
+    },
+    PhaseFinish {
+        phase: &'a str,
+        seconds: f64,
+    },
+    Error {
+        message: &'a str,
+    },
+}
+
+/// an event written to stderr by emit
+pub trait LogEvent: Serialize {
+    /// the line in text log format. None for the events not shown in text log format
+    fn text(&self) -> Option<String>;
+}
+
+impl LogEvent for Event<'_> {
+    fn text(&self) -> Option<String> {
+        Some(match self {
+            Event::PhaseStart { phase } => format!("{}...", phase),
+            Event::PhaseFinish { phase, seconds } => format!("{} took {}s", phase, seconds),
+            Event::Error { message } => format!("error: {}", message),
+        })
+    }
+}
+
+/// the line of the event in json log format. the event is tagged by `event` and `time` is added
+pub fn json_line(event: &impl LogEvent) -> String {
+    #[derive(Serialize)]
+    struct Line<'a, E> {
+        time: DateTime<Utc>,
+        #[serde(flatten)]
+        event: &'a E,
+    }
+    serde_json::to_string(&Line { time: Utc::now(), event }).unwrap()
+}
+
+/// writes the event to stderr in the format
+pub fn emit(format: LogFormat, event: impl LogEvent) {
+    match format {
+        LogFormat::Text => if let Some(text) = event.text() {
+            eprintln!("{}", text)
+        },
+        LogFormat::Json => eprintln!("{}", json_line(&event)),
+    }
+}
+
+/// removes `--log-format <text|json>` at the head of the arguments and returns the format.
+/// exits if the format is invalid
+pub fn parse_log_format(args: &mut Vec<String>) -> LogFormat {
+    if args.get(1).map(|x| x.as_str()) != Some("--log-format") {
+        return LogFormat::Text
+    }
+    let log_format = match args.get(2).map(|x| x.as_str()) {
+        Some("text") => LogFormat::Text,
+        Some("json") => LogFormat::Json,
+        _ => {
+            eprintln!("invalid log-format. must be either text or json");
+            std::process::exit(-1);
+        },
+    };
+    args.drain(1..3);
+    log_format
+}
+
+/// reports panics as error events instead of the default message in json log format
+/// so that every line of stderr is json
+pub fn report_panics(format: LogFormat) {
+    if format == LogFormat::Json {
+        on_panic(move |message| emit(format, Event::Error { message }));
+    }
+}
+
+/// calls `report` with the message and the location of a panic instead of the default hook
+fn on_panic(report: impl Fn(&str) + Send + Sync + 'static) {
+    std::panic::set_hook(Box::new(move |info| {
+        let payload = info.payload();
+        let message = payload.downcast_ref::<&str>().copied()
+            .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+            .unwrap_or("panicked");
+        let message = match info.location() {
+            Some(location) => format!("{} at {}", message, location),
+            None => message.to_owned(),
+        };
+        report(&message);
+    }));
+}
+
+/// reports start of the phase now and finish of that on finish
+pub struct Phase {
+    format: LogFormat,
+    name: &'static str,
+    start: Instant,
+}
+
+impl Phase {
+    pub fn start(format: LogFormat, name: &'static str) -> Phase {
+        emit(format, Event::PhaseStart { phase: name });
+        Phase {
+            format,
+            name,
+            start: Instant::now(),
+        }
+    }
+
+    pub fn finish(self) {
+        emit(self.format, Event::PhaseFinish {
+            phase: self.name,
+            seconds: (Instant::now() - self.start).as_secs_f64(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|&arg| arg.to_owned()).collect()
+    }
+
+    #[test]
+    fn log_format_removed() {
+        let mut json = args(&["sort-ranking", "--log-format", "json", "in.bin"]);
+        assert!(parse_log_format(&mut json) == LogFormat::Json);
+        assert_eq!(json, args(&["sort-ranking", "in.bin"]));
+    }
+
+    #[test]
+    fn json_line_tagged() {
+        let line: serde_json::Value = serde_json::from_str(&json_line(&Event::Error { message: "broken" })).unwrap();
+        assert_eq!(line["event"], "error");
+        assert_eq!(line["message"], "broken");
+        assert!(line["time"].is_string());
+    }
+
+    #[test]
+    fn log_format_default() {
+        let mut text = args(&["html-gen", "in.bin", "out"]);
+        assert!(parse_log_format(&mut text) == LogFormat::Text);
+        assert_eq!(text, args(&["html-gen", "in.bin", "out"]));
+    }
+}
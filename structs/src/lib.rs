@@ -1,3 +1,8 @@
+pub mod date_arg;
+pub mod events;
+pub mod compression;
+pub mod bin;
+
 use serde::{Serialize, Deserialize};
 use chrono::{DateTime, FixedOffset, Utc};
 
@@ -56,6 +61,7 @@ pub struct RankingVideoDataBin {
     pub ranking_counter: u64,
 }
 
+#[derive(Clone)]
 #[derive(Serialize, Deserialize)]
 pub struct NewVideoInfo {
     pub last_modified: DateTime<Utc>,
@@ -74,3 +80,72 @@ pub struct NewVideoInfo {
     pub tags: Vec<String>,
     pub genre: Option<String>,
 }
+
+
+/// NewVideoInfo got with a part of fields. the fields not got are None.
+/// content_id, start_time and last_modified are always got.
+/// which fields are got is written in the bin with the videos.
+#[derive(Clone)]
+#[derive(Serialize, Deserialize)]
+pub struct PartialVideoInfo {
+    pub last_modified: DateTime<Utc>,
+    pub content_id: String,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub view_counter: Option<u32>,
+    pub mylist_counter: Option<u32>,
+    pub length_seconds: Option<std::time::Duration>,
+    pub thumbnail_url: Option<String>,
+    pub start_time: DateTime<Utc>,
+    pub last_res_body: Option<String>,
+    pub comment_counter: Option<u32>,
+    pub last_comment_time: Option<DateTime<Utc>>,
+    pub category_tags: Option<String>,
+    pub tags: Option<Vec<String>>,
+    pub genre: Option<String>,
+}
+
+impl From<NewVideoInfo> for PartialVideoInfo {
+    fn from(video: NewVideoInfo) -> Self {
+        PartialVideoInfo {
+            last_modified: video.last_modified,
+            content_id: video.content_id,
+            title: Some(video.title),
+            description: video.description,
+            view_counter: Some(video.view_counter),
+            mylist_counter: Some(video.mylist_counter),
+            length_seconds: Some(video.length_seconds),
+            thumbnail_url: video.thumbnail_url,
+            start_time: video.start_time,
+            last_res_body: video.last_res_body,
+            comment_counter: Some(video.comment_counter),
+            last_comment_time: video.last_comment_time,
+            category_tags: video.category_tags,
+            tags: Some(video.tags),
+            genre: video.genre,
+        }
+    }
+}
+
+impl PartialVideoInfo {
+    /// None if a field required by NewVideoInfo is not got
+    pub fn to_full(&self) -> Option<NewVideoInfo> {
+        Some(NewVideoInfo {
+            last_modified: self.last_modified,
+            content_id: self.content_id.clone(),
+            title: self.title.clone()?,
+            description: self.description.clone(),
+            view_counter: self.view_counter?,
+            mylist_counter: self.mylist_counter?,
+            length_seconds: self.length_seconds?,
+            thumbnail_url: self.thumbnail_url.clone(),
+            start_time: self.start_time,
+            last_res_body: self.last_res_body.clone(),
+            comment_counter: self.comment_counter?,
+            last_comment_time: self.last_comment_time,
+            category_tags: self.category_tags.clone(),
+            tags: self.tags.clone()?,
+            genre: self.genre.clone(),
+        })
+    }
+}